@@ -1,11 +1,46 @@
 use anchor_lang::prelude::*;
+use anchor_lang::system_program;
+use anchor_spl::associated_token::AssociatedToken;
 use anchor_spl::token::{self, Token, TokenAccount, Transfer};
+use anchor_spl::token_interface::{self, spl_token_2022::instruction::AuthorityType, TokenInterface, TransferChecked};
 
 declare_id!("7Gh4eFGmobz5ngu2U3bgZiQm2Adwm33dQTsUwzRb7wBi");
 
 // Hardcoded admin public key for security
 pub const ADMIN_PUBKEY: &str = "4nQVUxfFaFjmz9esZxkBUUxgjDCyCcHMarHU8Ek7nGjy";
 
+// Outcome index that resolves any market as Invalid, refunding every stake
+pub const INVALID_OUTCOME: u8 = u8::MAX;
+
+// Outcome indexes of the implicit YES/NO outcomes on binary markets
+pub const BINARY_YES_INDEX: u8 = 0;
+pub const BINARY_NO_INDEX: u8 = 1;
+
+// Outcome indexes of the implicit LONG/SHORT positions on scalar markets
+pub const SCALAR_LONG_INDEX: u8 = 0;
+pub const SCALAR_SHORT_INDEX: u8 = 1;
+
+// Swap fee on AMM trades, left in the pool for liquidity providers
+pub const AMM_FEE_BPS: u64 = 100;
+
+// Outcome prices returned by quote_prices are fractions of this
+pub const PRICE_SCALE: u64 = 1_000_000;
+
+// Fixed-point scale for the LMSR exp/ln math
+pub const FP_SCALE: u128 = 1_000_000_000_000;
+const FP_LN_2: i128 = 693_147_180_560;
+
+// Capacity of each side of an order book and of its fill event queue
+pub const MAX_ORDERS_PER_SIDE: usize = 32;
+pub const MAX_FILL_EVENTS: usize = 32;
+
+// Winners have this long after resolution to claim before dust can be swept
+pub const CLAIM_PERIOD_SECONDS: i64 = 90 * 24 * 60 * 60;
+
+// remaining_accounts per batch_stake entry: market, creator_profile, prediction, market_vault,
+// collateral_mint, user token account (or sol_vault), outcome_mint, user_outcome_token_account
+pub const BATCH_STAKE_ACCOUNTS: usize = 8;
+
 // Function to validate admin authority
 pub fn is_admin(admin_key: &Pubkey) -> bool {
     admin_key.to_string() == ADMIN_PUBKEY
@@ -39,6 +74,88 @@ pub struct RewardClaimedEvent {
     pub total_stake: u64,
 }
 
+#[event]
+pub struct OutcomeMintInitializedEvent {
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct PredictionTransferredEvent {
+    pub market: Pubkey,
+    pub from: Pubkey,
+    pub to: Pubkey,
+    pub outcome_index: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct PredictionClosedEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub amount: u64,
+}
+
+#[event]
+pub struct SharesTradedEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub is_buy: bool,
+    pub collateral_amount: u64,
+    pub shares: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct LiquidityChangedEvent {
+    pub provider: Pubkey,
+    pub market: Pubkey,
+    pub is_deposit: bool,
+    pub collateral_amount: u64,
+    pub lp_shares: u64,
+    pub lp_total_shares: u64,
+}
+
+#[event]
+pub struct CompleteSetEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub is_mint: bool,
+    pub amount: u64,
+    pub total_pool: u64,
+}
+
+#[event]
+pub struct OrderPlacedEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub order_id: u64,
+    pub side: u8,
+    pub price: u64,
+    pub filled: u64,
+    pub resting: u64,
+}
+
+#[event]
+pub struct OrderCancelledEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub order_id: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct SharesRedeemedEvent {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub payout: u64,
+}
+
 #[event]
 pub struct CreatorTierChangedEvent {
     pub creator: Pubkey,
@@ -49,6 +166,60 @@ pub struct CreatorTierChangedEvent {
     pub traction_score: u64,
 }
 
+#[event]
+pub struct TreasuryInitializedEvent {
+    pub treasury: Pubkey,
+    pub mint: Pubkey,
+}
+
+#[event]
+pub struct ProtocolFeeCollectedEvent {
+    pub market: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct TreasuryWithdrawnEvent {
+    pub treasury: Pubkey,
+    pub mint: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct CollateralMintUpdatedEvent {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub min_stake: u64,
+    pub enabled: bool,
+}
+
+#[event]
+pub struct MarketVaultSweptEvent {
+    pub market: Pubkey,
+    pub treasury: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+}
+
+#[event]
+pub struct MarketClosedEvent {
+    pub market: Pubkey,
+    pub creator: Pubkey,
+    pub closed_by: Pubkey,
+    pub total_pool: u64,
+    pub total_claimed: u64,
+}
+
+#[event]
+pub struct CreatorFeesWithdrawnEvent {
+    pub market: Pubkey,
+    pub creator: Pubkey,
+    pub amount: u64,
+}
+
 #[program]
 pub mod contracts {
     use super::*;
@@ -91,63 +262,143 @@ pub mod contracts {
         ai_confidence_score: f32,
         resolution_data: String,
     ) -> Result<()> {
-        let market = &mut ctx.accounts.market;
-        
-        require!(
-            ctx.accounts.ai_resolver.authority == ctx.accounts.resolver_authority.key(),
-            ErrorCode::Unauthorized
-        );
-        
-        require!(ctx.accounts.ai_resolver.active, ErrorCode::ResolverInactive);
-        
-        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
-        
-        let current_time = Clock::get()?.unix_timestamp;
-        require!(current_time >= market.deadline, ErrorCode::MarketNotExpired);
-        
-        require!(ai_confidence_score >= 0.85, ErrorCode::LowAIConfidence);
+        resolve_via_ai(ctx.accounts, ai_confidence_score, |market| {
+            if winning_outcome_index == INVALID_OUTCOME {
+                require!(
+                    market.market_type != MarketType::OpenEnded as u8,
+                    ErrorCode::NotTimeBoundMarket
+                );
+            } else {
+                require!(
+                    market.market_type == MarketType::TimeBound as u8
+                        || market.market_type == MarketType::Binary as u8,
+                    ErrorCode::NotTimeBoundMarket
+                );
+            }
+            
+            market.set_winning_outcome(Some(winning_outcome_index))
+        })?;
         
-        require!(
-            market.market_type == MarketType::TimeBound as u8,
-            ErrorCode::NotTimeBoundMarket
-        );
+        msg!("Market resolved by AI with outcome: {}, confidence: {}", winning_outcome_index, ai_confidence_score);
+        msg!("Resolution data: {}", resolution_data);
         
-        require!(
-            (winning_outcome_index as usize) < market.outcomes.len(),
-            ErrorCode::InvalidOutcomeIndex
-        );
+        Ok(())
+    }
 
-        market.winning_outcome = Some(winning_outcome_index);
-        market.resolved = true;
-        
-        ctx.accounts.ai_resolver.resolution_count = ctx.accounts.ai_resolver.resolution_count.checked_add(1).unwrap();
+    pub fn resolve_scalar_market_via_ai(
+        ctx: Context<ResolveMarketViaAI>,
+        resolved_value: i64,
+        ai_confidence_score: f32,
+        resolution_data: String,
+    ) -> Result<()> {
+        resolve_via_ai(ctx.accounts, ai_confidence_score, |market| {
+            require!(
+                market.market_type == MarketType::Scalar as u8,
+                ErrorCode::NotScalarMarket
+            );
+            
+            market.set_scalar_value(resolved_value);
+            Ok(())
+        })?;
         
-        msg!("Market resolved by AI with outcome: {}, confidence: {}", winning_outcome_index, ai_confidence_score);
+        msg!("Scalar market resolved by AI with value: {}, confidence: {}", resolved_value, ai_confidence_score);
         msg!("Resolution data: {}", resolution_data);
         
         Ok(())
     }
 
-    pub fn create_market(
-        ctx: Context<InitializeMarket>,
-        question: String,
-        outcomes: Vec<String>,
-        ai_score: f32,
-        ai_recommended_resolution_time: i64,
-        ai_classification: u8,
-        creator_metadata: String,
-        _creator_fee_bps: Option<u16>,  
-        ai_resolvable: Option<bool>, 
-    ) -> Result<()> {
+    pub fn create_market(ctx: Context<InitializeMarket>, params: CreateMarketParams) -> Result<()> {
+        let CreateMarketParams {
+            question,
+            outcomes,
+            ai_score,
+            ai_recommended_resolution_time,
+            ai_classification,
+            creator_metadata,
+            creator_fee_bps: _,
+            ai_resolvable,
+            fee_model,
+            scalar_lower_bound,
+            scalar_upper_bound,
+            trading_mode,
+            lmsr_liquidity,
+            native_sol,
+            stake_limits,
+        } = params;
+        
         require!(outcomes.len() <= 5, ErrorCode::TooManyOutcomes);
         require!(ai_score >= 0.7, ErrorCode::LowAIScore);
         
-        match ai_classification {
-            0 => {}, 
-            1 => {}, 
+        // Clients that omit the fee model keep the original whole-share fees
+        let fee_model = fee_model.unwrap_or(FeeModel::TotalShare as u8);
+        require!(
+            fee_model == FeeModel::TotalShare as u8 || fee_model == FeeModel::ProfitOnly as u8,
+            ErrorCode::InvalidFeeModel
+        );
+        
+        let outcomes = match ai_classification {
+            0 => outcomes, 
+            1 => outcomes, 
+            2 => {
+                // Binary markets always get the implicit YES/NO outcomes
+                require!(outcomes.is_empty(), ErrorCode::ImplicitOutcomes);
+                vec!["YES".to_string(), "NO".to_string()]
+            },
+            3 => {
+                // Scalar markets pay LONG/SHORT by where the value lands in the range
+                require!(outcomes.is_empty(), ErrorCode::ImplicitOutcomes);
+                vec!["LONG".to_string(), "SHORT".to_string()]
+            },
             _ => return Err(ErrorCode::InvalidMarketType.into()),
         };
         
+        let trading_mode = trading_mode.unwrap_or(TradingMode::Parimutuel as u8);
+        match trading_mode {
+            0 => {},
+            1 | 2 => require!(outcomes.len() >= 2, ErrorCode::InvalidTradingMode),
+            _ => return Err(ErrorCode::InvalidTradingMode.into()),
+        };
+        
+        // LMSR markets are funded up front by the creator with the maker's worst-case loss
+        let (lmsr_liquidity, lmsr_subsidy) = if trading_mode == TradingMode::Lmsr as u8 {
+            let b = lmsr_liquidity.ok_or(ErrorCode::LmsrLiquidityRequired)?;
+            require!(b > 0, ErrorCode::LmsrLiquidityRequired);
+            let subsidy = lmsr_subsidy(b, outcomes.len()).ok_or(ErrorCode::LmsrLiquidityRequired)?;
+            (b, subsidy)
+        } else {
+            (0, 0)
+        };
+        
+        // Share trading accounts are SPL Token only, so Token-2022 collateral is parimutuel
+        require!(
+            trading_mode == TradingMode::Parimutuel as u8
+                || ctx.accounts.token_program.key() == token::ID,
+            ErrorCode::Token2022Unsupported
+        );
+        
+        // Native markets stay parimutuel and keep the native mint, so outcome tokens get its 9 decimals
+        let native_sol = native_sol.unwrap_or(false);
+        if native_sol {
+            require!(
+                trading_mode == TradingMode::Parimutuel as u8,
+                ErrorCode::NativeSolUnsupported
+            );
+            require_keys_eq!(
+                ctx.accounts.mint.key(),
+                token::spl_token::native_mint::ID,
+                ErrorCode::InvalidNativeMint
+            );
+        }
+        
+        let (scalar_lower, scalar_upper) = if ai_classification == MarketType::Scalar as u8 {
+            let lower = scalar_lower_bound.ok_or(ErrorCode::InvalidScalarBounds)?;
+            let upper = scalar_upper_bound.ok_or(ErrorCode::InvalidScalarBounds)?;
+            require!(lower < upper, ErrorCode::InvalidScalarBounds);
+            (lower, upper)
+        } else {
+            (0, 0)
+        };
+        
         let clock = Clock::get()?;
         require!(
             ai_recommended_resolution_time >= clock.unix_timestamp,
@@ -178,6 +429,7 @@ pub mod contracts {
         
         let market = &mut ctx.accounts.market;
         market.creator = ctx.accounts.creator.key();
+        market.market_index = creator_profile.markets_created;
         market.question = question.clone();
         market.outcomes = outcomes;
         market.ai_score = ai_score;
@@ -185,14 +437,81 @@ pub mod contracts {
         market.deadline = ai_recommended_resolution_time;
         market.ai_suggested_deadline = ai_recommended_resolution_time;
         market.resolved = false;
+        market.resolved_at = 0;
         market.winning_outcome = None;
+        market.scalar_lower = scalar_lower;
+        market.scalar_upper = scalar_upper;
+        market.resolved_value = None;
+        market.payout_numerators = Vec::new();
+        market.invalid = false;
         market.total_pool = 0;
         market.creator_fee_bps = fee_bps;
         market.protocol_fee_bps = 50; 
+        market.creator_fees_accrued = 0;
+        market.fee_model = fee_model;
+        market.total_claimed = 0;
+        market.claimed_winning_stake = 0;
+        market.swept = false;
         market.stakes_per_outcome = vec![0; market.outcomes.len()]; 
+        market.outcome_mints = vec![Pubkey::default(); market.outcomes.len()];
+        market.trading_mode = trading_mode;
+        market.amm_reserves = vec![0; market.outcomes.len()];
+        market.outstanding_shares = vec![0; market.outcomes.len()];
+        market.lp_total_shares = 0;
+        market.lp_fees_accrued = 0;
+        market.lmsr_liquidity = lmsr_liquidity;
+        market.clob_escrow = 0;
+        market.native_sol = native_sol;
+        market.sol_vault_bump = 0;
+        let (min_stake, max_stake, max_position) = stake_limits
+            .unwrap_or_default()
+            .resolve(ctx.accounts.collateral_config.min_stake)?;
+        market.min_stake = min_stake;
+        market.max_stake = max_stake;
+        market.max_position = max_position;
         market.ai_resolvable = ai_resolvable.unwrap_or(true); 
         market.bump = ctx.bumps.market;
         
+        if native_sol {
+            let sol_vault = ctx.accounts.sol_vault.as_ref().ok_or(ErrorCode::SolVaultRequired)?;
+            
+            // The creator funds the SOL vault's rent-exempt reserve so stakes of any size can land
+            let cpi_accounts = system_program::Transfer {
+                from: ctx.accounts.creator.to_account_info(),
+                to: sol_vault.to_account_info(),
+            };
+            
+            let cpi_program = ctx.accounts.system_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            
+            system_program::transfer(cpi_ctx, Rent::get()?.minimum_balance(0))?;
+            
+            market.sol_vault_bump = ctx.bumps.sol_vault.ok_or(ErrorCode::SolVaultRequired)?;
+        }
+        
+        if lmsr_subsidy > 0 {
+            let creator_token_account = ctx
+                .accounts
+                .creator_token_account
+                .as_ref()
+                .ok_or(ErrorCode::LmsrLiquidityRequired)?;
+            
+            let cpi_accounts = TransferChecked {
+                from: creator_token_account.to_account_info(),
+                mint: ctx.accounts.mint.to_account_info(),
+                to: ctx.accounts.market_vault.to_account_info(),
+                authority: ctx.accounts.creator.to_account_info(),
+            };
+            
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            
+            token_interface::transfer_checked(cpi_ctx, lmsr_subsidy, ctx.accounts.mint.decimals)?;
+            
+            market.total_pool = lmsr_subsidy;
+            msg!("LMSR subsidy deposited: {} (b = {})", lmsr_subsidy, lmsr_liquidity);
+        }
+        
         creator_profile.last_created_at = clock.unix_timestamp;
         creator_profile.markets_created = creator_profile.markets_created.checked_add(1).unwrap();
 
@@ -210,104 +529,209 @@ pub mod contracts {
         Ok(())
     }
 
+    // `max_outcome_share_bps` rejects the stake if the outcome's share of the pool,
+    // including this stake, has grown past what the user quoted
     pub fn stake_prediction(
         ctx: Context<StakePrediction>,
         outcome_index: u8,
         amount: u64,
+        max_outcome_share_bps: Option<u16>,
+    ) -> Result<()> {
+        process_stake(ctx, outcome_index, amount, max_outcome_share_bps)
+    }
+
+    pub fn stake_yes(
+        ctx: Context<StakePrediction>,
+        amount: u64,
+        max_outcome_share_bps: Option<u16>,
     ) -> Result<()> {
-        let market = &mut ctx.accounts.market;
         require!(
-            (outcome_index as usize) < market.outcomes.len(),
-            ErrorCode::InvalidOutcomeIndex
+            ctx.accounts.market.market_type == MarketType::Binary as u8,
+            ErrorCode::NotBinaryMarket
         );
         
-        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
-        
-        let current_time = Clock::get()?.unix_timestamp;
-        require!(market.deadline > current_time, ErrorCode::MarketExpired);
+        process_stake(ctx, BINARY_YES_INDEX, amount, max_outcome_share_bps)
+    }
 
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.user_token_account.to_account_info(),
-            to: ctx.accounts.market_vault.to_account_info(),
-            authority: ctx.accounts.user.to_account_info(),
-        };
-        
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        
-        token::transfer(cpi_ctx, amount)?;
+    pub fn stake_no(
+        ctx: Context<StakePrediction>,
+        amount: u64,
+        max_outcome_share_bps: Option<u16>,
+    ) -> Result<()> {
+        require!(
+            ctx.accounts.market.market_type == MarketType::Binary as u8,
+            ErrorCode::NotBinaryMarket
+        );
         
-        let prediction = &mut ctx.accounts.prediction;
-        prediction.market = market.key();
-        prediction.user = ctx.accounts.user.key();
-        prediction.outcome_index = outcome_index;
-        prediction.amount = amount;
-        prediction.timestamp = current_time;
-        prediction.claimed = false;
-        prediction.bump = ctx.bumps.prediction;
+        process_stake(ctx, BINARY_NO_INDEX, amount, max_outcome_share_bps)
+    }
+
+    pub fn batch_stake<'info>(
+        ctx: Context<'_, '_, 'info, 'info, BatchStake<'info>>,
+        entries: Vec<BatchStakeEntry>,
+    ) -> Result<()> {
+        check_batch_entries(&entries, ctx.remaining_accounts)?;
         
-        market.total_pool = market.total_pool.checked_add(amount).unwrap();
+        let accounts = ctx.accounts;
         
-        if market.stakes_per_outcome.len() < market.outcomes.len() {
-            market.stakes_per_outcome = vec![0; market.outcomes.len()];
+        for entry in &entries {
+            let start = entry.group as usize * BATCH_STAKE_ACCOUNTS;
+            let group = &ctx.remaining_accounts[start..start + BATCH_STAKE_ACCOUNTS];
+            stake_batch_entry(accounts, entry, group)?;
         }
         
-        market.stakes_per_outcome[outcome_index as usize] = 
-            market.stakes_per_outcome[outcome_index as usize].checked_add(amount).unwrap();
+        msg!("Batch staked into {} markets", entries.len());
+        Ok(())
+    }
+
+    // Opt-in alternative to outcome tokens: the stake is represented by a 1-of-1
+    // position NFT, and whoever holds it can claim with claim_position_nft
+    pub fn stake_prediction_nft(
+        ctx: Context<StakePredictionNft>,
+        outcome_index: u8,
+        amount: u64,
+        max_outcome_share_bps: Option<u16>,
+    ) -> Result<()> {
+        require!(!ctx.accounts.market.native_sol, ErrorCode::NativeSolUnsupported);
+        check_stake_request(&ctx.accounts.market, outcome_index, amount, max_outcome_share_bps)?;
         
-        let creator_profile = &mut ctx.accounts.creator_profile;
-        creator_profile.total_volume = creator_profile.total_volume.checked_add(amount).unwrap();
+        let collateral = CollateralVault {
+            market_vault: &ctx.accounts.market_vault,
+            mint: &ctx.accounts.collateral_mint,
+            sol_vault: None,
+            token_program: &ctx.accounts.token_program,
+            system_program: None,
+        };
         
-        creator_profile.traction_score = creator_profile.traction_score.checked_add(amount / 1000 + 1).unwrap();
+        let amount = collateral.deposit(
+            &ctx.accounts.market,
+            ctx.accounts.user_token_account.to_account_info(),
+            ctx.accounts.token_authority.to_account_info(),
+            amount,
+        )?;
         
-        if let Some(user_profile) = &mut ctx.accounts.user_profile {
-            user_profile.total_staked = user_profile.total_staked.checked_add(amount).unwrap();
-            user_profile.total_predictions = user_profile.total_predictions.checked_add(1).unwrap();
-            user_profile.last_active_ts = current_time;
+        {
+            let market = &ctx.accounts.market;
+            let index_bytes = market.market_index.to_le_bytes();
+            let seeds = market.signer_seeds(&index_bytes);
+            let signer = &[&seeds[..]];
+            
+            let cpi_accounts = token_interface::MintTo {
+                mint: ctx.accounts.position_mint.to_account_info(),
+                to: ctx.accounts.user_position_token_account.to_account_info(),
+                authority: market.to_account_info(),
+            };
+            
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            
+            token_interface::mint_to(cpi_ctx, 1)?;
+            
+            // Dropping the mint authority fixes the supply at one
+            let cpi_accounts = token_interface::SetAuthority {
+                current_authority: market.to_account_info(),
+                account_or_mint: ctx.accounts.position_mint.to_account_info(),
+            };
+            
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            
+            token_interface::set_authority(cpi_ctx, AuthorityType::MintTokens, None)?;
         }
         
-        let previous_tier = creator_profile.tier;
-        if let Some(creator_tier_threshold) = get_next_tier_threshold(creator_profile) {
-            if creator_profile.total_volume >= creator_tier_threshold.0 && 
-               creator_profile.markets_created >= creator_tier_threshold.1 && 
-               creator_profile.traction_score >= creator_tier_threshold.2 {
-                creator_profile.tier = creator_profile.tier.checked_add(1).unwrap();
-                
-                if creator_profile.tier != previous_tier {
-                    emit!(CreatorTierChangedEvent {
-                        creator: creator_profile.creator,
-                        previous_tier,
-                        new_tier: creator_profile.tier,
-                        markets_count: creator_profile.markets_created,
-                        total_volume: creator_profile.total_volume,
-                        traction_score: creator_profile.traction_score,
-                    });
-                }
-            }
-        }
+        let prediction = &mut ctx.accounts.prediction;
+        prediction.user = ctx.accounts.beneficiary.key();
+        prediction.position_mint = ctx.accounts.position_mint.key();
+        prediction.bump = ctx.bumps.prediction;
         
-        emit!(PredictionStakedEvent {
-            user: ctx.accounts.user.key(),
-            market: market.key(),
+        record_prediction(
+            &mut ctx.accounts.market,
+            prediction,
+            &mut ctx.accounts.creator_profile,
+            ctx.accounts.user_profile.as_mut(),
             outcome_index,
             amount,
-            timestamp: current_time,
-        });
-        
-        msg!("Staked {} on outcome {}", amount, outcome_index);
-        Ok(())
+        )
     }
 
-    pub fn vote_market_outcome(
-        ctx: Context<VoteMarketOutcome>,
-        outcome_index: u8,
-    ) -> Result<()> {
+    pub fn transfer_prediction(ctx: Context<TransferPrediction>) -> Result<()> {
         let market = &ctx.accounts.market;
+        let prediction = &ctx.accounts.prediction;
+        let new_owner = ctx.accounts.new_owner.key();
         
-        require!(
-            market.market_type == MarketType::OpenEnded as u8,
-            ErrorCode::NotOpenEndedMarket
-        );
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require_keys_neq!(new_owner, ctx.accounts.user.key(), ErrorCode::InvalidTransferRecipient);
+        
+        // Votes are weighted by prediction, so positions only move before voting opens
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(market.deadline > current_time, ErrorCode::MarketExpired);
+        
+        // Claims follow the outcome tokens or the position NFT, so they move with the prediction
+        let claim_amount = if prediction.position_mint != Pubkey::default() {
+            require_keys_eq!(ctx.accounts.claim_mint.key(), prediction.position_mint, ErrorCode::InvalidPositionMint);
+            1
+        } else {
+            require!(
+                market.outcome_index_for_mint(&ctx.accounts.claim_mint.key())? == prediction.outcome_index,
+                ErrorCode::InvalidOutcomeMint
+            );
+            prediction.amount
+        };
+        
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.user_claim_token_account.to_account_info(),
+            mint: ctx.accounts.claim_mint.to_account_info(),
+            to: ctx.accounts.new_owner_claim_token_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token_interface::transfer_checked(cpi_ctx, claim_amount, ctx.accounts.claim_mint.decimals)?;
+        
+        let new_prediction = &mut ctx.accounts.new_prediction;
+        new_prediction.user = new_owner;
+        new_prediction.market = prediction.market;
+        new_prediction.outcome_index = prediction.outcome_index;
+        new_prediction.amount = prediction.amount;
+        new_prediction.timestamp = prediction.timestamp;
+        new_prediction.position_mint = prediction.position_mint;
+        new_prediction.bump = ctx.bumps.new_prediction;
+        
+        if let Some(user_profile) = &mut ctx.accounts.user_profile {
+            user_profile.total_staked = user_profile.total_staked.saturating_sub(prediction.amount);
+            user_profile.total_predictions = user_profile.total_predictions.saturating_sub(1);
+            user_profile.last_active_ts = current_time;
+        }
+        
+        if let Some(new_owner_profile) = &mut ctx.accounts.new_owner_profile {
+            new_owner_profile.total_staked = new_owner_profile.total_staked.checked_add(prediction.amount).unwrap();
+            new_owner_profile.total_predictions = new_owner_profile.total_predictions.checked_add(1).unwrap();
+        }
+        
+        emit!(PredictionTransferredEvent {
+            market: market.key(),
+            from: ctx.accounts.user.key(),
+            to: new_owner,
+            outcome_index: prediction.outcome_index,
+            amount: prediction.amount,
+        });
+        
+        msg!("Prediction transferred from {} to {}", ctx.accounts.user.key(), new_owner);
+        Ok(())
+    }
+
+    pub fn vote_market_outcome(
+        ctx: Context<VoteMarketOutcome>,
+        outcome_index: u8,
+    ) -> Result<()> {
+        let market = &ctx.accounts.market;
+        
+        require!(
+            market.market_type == MarketType::OpenEnded as u8,
+            ErrorCode::NotOpenEndedMarket
+        );
         
         require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
         
@@ -325,7 +749,7 @@ pub mod contracts {
         );
         
         require!(
-            (outcome_index as usize) < market.outcomes.len(),
+            (outcome_index as usize) < market.outcomes.len() || outcome_index == INVALID_OUTCOME,
             ErrorCode::InvalidOutcomeIndex
         );
         
@@ -350,162 +774,284 @@ pub mod contracts {
         
         require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
         
-        if market.market_type == MarketType::TimeBound as u8 {
-            // Validate outcome index
-            let outcome = winning_outcome_index.ok_or(ErrorCode::WinningOutcomeRequired)?;
-            require!(
-                (outcome as usize) < market.outcomes.len(),
-                ErrorCode::InvalidOutcomeIndex
-            );
-            
-            market.winning_outcome = winning_outcome_index;
-        } else {
-            market.winning_outcome = winning_outcome_index;
+        require!(
+            market.market_type != MarketType::Scalar as u8
+                || winning_outcome_index == Some(INVALID_OUTCOME),
+            ErrorCode::ScalarValueRequired
+        );
+        
+        if market.market_type != MarketType::OpenEnded as u8 {
+            winning_outcome_index.ok_or(ErrorCode::WinningOutcomeRequired)?;
         }
         
+        // Open-ended markets without a winner resolve as Invalid
+        market.set_winning_outcome(winning_outcome_index)?;
+        
         market.resolved = true;
+        market.resolved_at = Clock::get()?.unix_timestamp;
         
         msg!("Market resolved with winning outcome: {:?}", market.winning_outcome);
         Ok(())
     }
 
-    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
-        let market = &ctx.accounts.market;
-        let prediction = &mut ctx.accounts.prediction;
+    pub fn resolve_scalar_market(
+        ctx: Context<ResolveMarket>,
+        resolved_value: i64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
         
-        require!(market.resolved, ErrorCode::MarketNotResolved);
-        require!(!prediction.claimed, ErrorCode::RewardAlreadyClaimed);
-
-        let winning_outcome = market.winning_outcome.ok_or(ErrorCode::NoWinningOutcome)?;
         require!(
-            prediction.outcome_index == winning_outcome,
-            ErrorCode::NotWinningPrediction
+            market.market_type == MarketType::Scalar as u8,
+            ErrorCode::NotScalarMarket
         );
         
-        let total_winning_stakes = market.stakes_per_outcome[winning_outcome as usize];
-        require!(total_winning_stakes > 0, ErrorCode::InvalidDistribution);
+        market.set_scalar_value(resolved_value);
+        market.resolved = true;
+        market.resolved_at = Clock::get()?.unix_timestamp;
         
-        let user_stake = prediction.amount;
-        let total_pool = market.total_pool;
+        msg!(
+            "Scalar market resolved with value {} in range [{}, {}]",
+            resolved_value,
+            market.scalar_lower,
+            market.scalar_upper
+        );
+        Ok(())
+    }
+
+    pub fn resolve_market_with_payouts(
+        ctx: Context<ResolveMarket>,
+        payout_numerators: Vec<u64>,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
         
-        let user_share_numerator = (user_stake as u128).checked_mul(total_pool as u128).unwrap();
-        let user_share = user_share_numerator.checked_div(total_winning_stakes as u128).unwrap();
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
         
-        let creator_fee_amount = user_share
-            .checked_mul(market.creator_fee_bps as u128)
-            .unwrap()
-            .checked_div(10000)
-            .unwrap();
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
         
-        let protocol_fee_amount = user_share
-            .checked_mul(market.protocol_fee_bps as u128)
-            .unwrap()
-            .checked_div(10000)
-            .unwrap();
+        market.set_payout_numerators(payout_numerators)?;
+        market.resolved = true;
+        market.resolved_at = Clock::get()?.unix_timestamp;
         
-        let reward_amount = user_share
-            .checked_sub(creator_fee_amount)
-            .unwrap()
-            .checked_sub(protocol_fee_amount)
-            .unwrap() as u64;
+        msg!("Market resolved with payout vector {:?}", market.payout_numerators);
+        Ok(())
+    }
+
+    pub fn claim_reward(ctx: Context<ClaimReward>) -> Result<()> {
+        // Whoever holds the outcome tokens owns the claim; they are burned for the payout
+        let outcome_index = ctx
+            .accounts
+            .market
+            .outcome_index_for_mint(&ctx.accounts.outcome_mint.key())?;
         
-        let market_key = market.key();
-        let seeds = &[
-            b"market".as_ref(),
-            market_key.as_ref(),
-            &[market.bump],
-        ];
-        let signer = &[&seeds[..]];
+        let user_stake = ctx.accounts.user_outcome_token_account.amount;
+        require!(user_stake > 0, ErrorCode::NoOutcomeTokens);
         
-        // Transfer rewards to user
-        {
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.market_vault.to_account_info(),
-                to: ctx.accounts.user_token_account.to_account_info(),
-                authority: ctx.accounts.market.to_account_info(),
-            };
-            
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::transfer(cpi_ctx, reward_amount)?;
-        }
+        let cpi_accounts = token_interface::Burn {
+            mint: ctx.accounts.outcome_mint.to_account_info(),
+            from: ctx.accounts.user_outcome_token_account.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
         
-        if creator_fee_amount > 0 {
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.market_vault.to_account_info(),
-                to: ctx.accounts.creator_token_account.to_account_info(),
-                authority: ctx.accounts.market.to_account_info(),
-            };
-            
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::transfer(cpi_ctx, creator_fee_amount as u64)?;
-        }
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
         
-        if protocol_fee_amount > 0 {
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.market_vault.to_account_info(),
-                to: ctx.accounts.protocol_fee_account.to_account_info(),
-                authority: ctx.accounts.market.to_account_info(),
-            };
-            
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-            
-            token::transfer(cpi_ctx, protocol_fee_amount as u64)?;
-        }
+        token_interface::burn(cpi_ctx, user_stake)?;
         
-        prediction.claimed = true;
+        let collateral = CollateralVault {
+            market_vault: &ctx.accounts.market_vault,
+            mint: &ctx.accounts.collateral_mint,
+            sol_vault: ctx.accounts.sol_vault.as_ref(),
+            token_program: &ctx.accounts.token_program,
+            system_program: Some(&ctx.accounts.system_program),
+        };
+        let destination = collateral.account_for(
+            &ctx.accounts.market,
+            &ctx.accounts.user.to_account_info(),
+            ctx.accounts.user_token_account.as_ref(),
+        )?;
         
-        if let Some(user_profile) = &mut ctx.accounts.user_profile {
-            user_profile.total_winnings = user_profile.total_winnings.checked_add(reward_amount).unwrap();
-            user_profile.winning_predictions = user_profile.winning_predictions.checked_add(1).unwrap();
-            user_profile.last_active_ts = Clock::get()?.unix_timestamp;
-        }
+        let total_pool = ctx.accounts.market.total_pool;
+        let reward_amount = pay_winning_claim(
+            &mut ctx.accounts.market,
+            &collateral,
+            destination,
+            &ctx.accounts.protocol_treasury,
+            outcome_index,
+            user_stake,
+        )?;
         
-        if let Some(creator_profile) = &mut ctx.accounts.creator_profile {
-            creator_profile.traction_score = creator_profile.traction_score.checked_add(reward_amount / 500 + 5).unwrap();
-            
-            let previous_tier = creator_profile.tier;
-            if let Some(creator_tier_threshold) = get_next_tier_threshold(creator_profile) {
-                if creator_profile.total_volume >= creator_tier_threshold.0 && 
-                   creator_profile.markets_created >= creator_tier_threshold.1 && 
-                   creator_profile.traction_score >= creator_tier_threshold.2 {
-                    creator_profile.tier = creator_profile.tier.checked_add(1).unwrap();
-                    
-                    if creator_profile.tier != previous_tier {
-                        emit!(CreatorTierChangedEvent {
-                            creator: creator_profile.creator,
-                            previous_tier,
-                            new_tier: creator_profile.tier,
-                            markets_count: creator_profile.markets_created,
-                            total_volume: creator_profile.total_volume,
-                            traction_score: creator_profile.traction_score,
-                        });
-                    }
-                }
-            }
-        }
+        record_claim(
+            ctx.accounts.user_profile.as_mut(),
+            ctx.accounts.creator_profile.as_mut(),
+            reward_amount,
+        )?;
 
         emit!(RewardClaimedEvent {
             user: ctx.accounts.user.key(),
-            market: market.key(),
+            market: ctx.accounts.market.key(),
             amount: reward_amount,
-            outcome_index: prediction.outcome_index,
+            outcome_index,
             winning_stake: user_stake,
             total_stake: total_pool,
         });
         
-        msg!(
-            "Reward claimed: total={}, user={}, creator_fee={}, protocol_fee={}",
-            user_share,
+        Ok(())
+    }
+
+    pub fn claim_position_nft(ctx: Context<ClaimPositionNft>) -> Result<()> {
+        let outcome_index = ctx.accounts.prediction.outcome_index;
+        let user_stake = ctx.accounts.prediction.amount;
+        
+        // The position NFT is the claim; the original staker's wallet plays no part
+        let cpi_accounts = token_interface::Burn {
+            mint: ctx.accounts.position_mint.to_account_info(),
+            from: ctx.accounts.holder_position_token_account.to_account_info(),
+            authority: ctx.accounts.holder.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token_interface::burn(cpi_ctx, 1)?;
+        
+        let collateral = CollateralVault {
+            market_vault: &ctx.accounts.market_vault,
+            mint: &ctx.accounts.collateral_mint,
+            sol_vault: None,
+            token_program: &ctx.accounts.token_program,
+            system_program: None,
+        };
+        
+        let total_pool = ctx.accounts.market.total_pool;
+        let reward_amount = pay_winning_claim(
+            &mut ctx.accounts.market,
+            &collateral,
+            ctx.accounts.holder_token_account.to_account_info(),
+            &ctx.accounts.protocol_treasury,
+            outcome_index,
+            user_stake,
+        )?;
+        
+        record_claim(
+            ctx.accounts.holder_profile.as_mut(),
+            ctx.accounts.creator_profile.as_mut(),
             reward_amount,
-            creator_fee_amount,
-            protocol_fee_amount
+        )?;
+        
+        emit!(RewardClaimedEvent {
+            user: ctx.accounts.holder.key(),
+            market: ctx.accounts.market.key(),
+            amount: reward_amount,
+            outcome_index,
+            winning_stake: user_stake,
+            total_stake: total_pool,
+        });
+        
+        Ok(())
+    }
+
+    pub fn creator_withdraw(ctx: Context<CreatorWithdraw>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let unused_subsidy = market.lmsr_unused_subsidy()?;
+        let amount = market.creator_fees_accrued.checked_add(unused_subsidy).unwrap();
+        
+        require!(amount > 0, ErrorCode::NoCreatorFees);
+        
+        let market_key = market.key();
+        let collateral = CollateralVault {
+            market_vault: &ctx.accounts.market_vault,
+            mint: &ctx.accounts.collateral_mint,
+            sol_vault: ctx.accounts.sol_vault.as_ref(),
+            token_program: &ctx.accounts.token_program,
+            system_program: Some(&ctx.accounts.system_program),
+        };
+        let destination = collateral.account_for(
+            market,
+            &ctx.accounts.creator.to_account_info(),
+            ctx.accounts.creator_token_account.as_ref(),
+        )?;
+        
+        collateral.pay(market, destination, amount)?;
+        
+        ctx.accounts.market.creator_fees_accrued = 0;
+        ctx.accounts.market.total_pool = ctx.accounts.market.total_pool.checked_sub(unused_subsidy).unwrap();
+        
+        emit!(CreatorFeesWithdrawnEvent {
+            market: market_key,
+            creator: ctx.accounts.creator.key(),
+            amount,
+        });
+        
+        msg!("Creator withdrew {} in accrued fees", amount);
+        Ok(())
+    }
+
+    pub fn sweep_market_vault(ctx: Context<SweepMarketVault>) -> Result<()> {
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let market = &ctx.accounts.market;
+        
+        require!(market.resolved, ErrorCode::MarketNotResolved);
+        require!(!market.swept, ErrorCode::MarketVaultSwept);
+        
+        let all_claimed = !market.has_unclaimed_payouts();
+        
+        let claim_deadline = market.resolved_at.checked_add(CLAIM_PERIOD_SECONDS).unwrap();
+        require!(
+            all_claimed || Clock::get()?.unix_timestamp > claim_deadline,
+            ErrorCode::ClaimPeriodActive
         );
         
+        let collateral = CollateralVault {
+            market_vault: &ctx.accounts.market_vault,
+            mint: &ctx.accounts.collateral_mint,
+            sol_vault: ctx.accounts.sol_vault.as_ref(),
+            token_program: &ctx.accounts.token_program,
+            system_program: Some(&ctx.accounts.system_program),
+        };
+        
+        let vault_balance = collateral.balance(market)?;
+        let dust = ctx.accounts.market.sweep(vault_balance)?;
+        
+        let market = &ctx.accounts.market;
+        if dust > 0 {
+            collateral.pay_treasury(market, &ctx.accounts.protocol_treasury, dust)?;
+        }
+        
+        emit!(MarketVaultSweptEvent {
+            market: market.key(),
+            treasury: ctx.accounts.protocol_treasury.key(),
+            amount: dust,
+            total_claimed: market.total_claimed,
+        });
+        
+        msg!("Swept {} from market vault to treasury", dust);
+        Ok(())
+    }
+
+    // Claims follow the outcome tokens, so the stake record can go once the market resolves
+    pub fn close_losing_prediction(ctx: Context<CloseLosingPrediction>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        let prediction = &ctx.accounts.prediction;
+        
+        market.check_prediction_closable(prediction)?;
+        
+        if let Some(user_profile) = &mut ctx.accounts.user_profile {
+            user_profile.last_active_ts = Clock::get()?.unix_timestamp;
+        }
+        
+        emit!(PredictionClosedEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            outcome_index: prediction.outcome_index,
+            amount: prediction.amount,
+        });
+        
+        msg!("Prediction closed, rent returned to {}", ctx.accounts.user.key());
         Ok(())
     }
 
@@ -516,7 +1062,47 @@ pub mod contracts {
         
         require!(market.resolved, ErrorCode::MarketNotResolved);
         
-        msg!("Market closed");
+        let market_key = market.key();
+        let index_bytes = market.market_index.to_le_bytes();
+        let seeds = market.signer_seeds(&index_bytes);
+        let signer = &[&seeds[..]];
+        
+        // The creator paid rent for the vault at create_market, so it goes back to them
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.market_vault.to_account_info(),
+            destination: ctx.accounts.creator.to_account_info(),
+            authority: ctx.accounts.market.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        
+        token_interface::close_account(cpi_ctx)?;
+        
+        if market.native_sol {
+            let collateral = CollateralVault {
+                market_vault: &ctx.accounts.market_vault,
+                mint: &ctx.accounts.collateral_mint,
+                sol_vault: ctx.accounts.sol_vault.as_ref(),
+                token_program: &ctx.accounts.token_program,
+                system_program: Some(&ctx.accounts.system_program),
+            };
+            require!(collateral.balance(market)? == 0, ErrorCode::MarketVaultNotEmpty);
+            
+            // Only the rent-exempt reserve is left, and the creator funded it
+            let (sol_vault, _) = collateral.native()?;
+            collateral.pay(market, ctx.accounts.creator.to_account_info(), sol_vault.lamports())?;
+        }
+        
+        emit!(MarketClosedEvent {
+            market: market_key,
+            creator: market.creator,
+            closed_by: ctx.accounts.admin.key(),
+            total_pool: market.total_pool,
+            total_claimed: market.total_claimed,
+        });
+        
+        msg!("Market {} and its vault closed", market_key);
         Ok(())
     }
 
@@ -565,8 +1151,9 @@ pub mod contracts {
         
         let vote_result = &mut ctx.accounts.vote_result;
         vote_result.market = market.key();
-        vote_result.vote_tallies = vec![0; market.outcomes.len()];
-        vote_result.stake_weights = vec![0; market.outcomes.len()];
+        // The extra last slot tallies votes for INVALID_OUTCOME
+        vote_result.vote_tallies = vec![0; market.outcomes.len() + 1];
+        vote_result.stake_weights = vec![0; market.outcomes.len() + 1];
         vote_result.vote_count = 0;
         vote_result.resolution_proposed = false;
         vote_result.proposed_outcome = None;
@@ -605,22 +1192,15 @@ pub mod contracts {
         );
         
         if vote_result.challenge_count > 0 {
-            let mut max_stake = 0;
-            let mut winning_index = 0;
-            
-            for (i, &stake) in vote_result.stake_weights.iter().enumerate() {
-                if stake > max_stake {
-                    max_stake = stake;
-                    winning_index = i;
-                }
-            }
+            let winning_index = vote_result.leading_outcome(market.outcomes.len());
             
-            vote_result.proposed_outcome = Some(winning_index as u8);
+            vote_result.proposed_outcome = Some(winning_index);
             msg!("Resolution determined by stake-weighted vote: outcome {}", winning_index);
         }
         
-        market.winning_outcome = vote_result.proposed_outcome;
+        market.set_winning_outcome(vote_result.proposed_outcome)?;
         market.resolved = true;
+        market.resolved_at = clock.unix_timestamp;
         vote_result.finalized = true;
         
         msg!("Market resolution finalized with outcome {:?}", market.winning_outcome);
@@ -648,8 +1228,21 @@ pub mod contracts {
             ErrorCode::Unauthorized
         );
         
+        // NFT-backed predictions hold no outcome tokens, so they vote with the position NFT
+        let has_nft = prediction.position_mint != Pubkey::default();
+        if has_nft {
+            require_keys_eq!(ctx.accounts.claim_mint.key(), prediction.position_mint, ErrorCode::InvalidPositionMint);
+        } else {
+            require!(
+                market.outcome_index_for_mint(&ctx.accounts.claim_mint.key())? == prediction.outcome_index,
+                ErrorCode::InvalidOutcomeMint
+            );
+        }
+        let weight = prediction.vote_weight(ctx.accounts.voter_claim_token_account.amount);
+        let escrow_amount = if has_nft { 1 } else { weight };
+        
         require!(
-            prediction.amount > 0,
+            weight > 0,
             ErrorCode::InsufficientStake
         );
         
@@ -667,25 +1260,75 @@ pub mod contracts {
         );
         
         require!(
-            (outcome_index as usize) < market.outcomes.len(),
+            (outcome_index as usize) < market.outcomes.len() || outcome_index == INVALID_OUTCOME,
             ErrorCode::InvalidOutcomeIndex
         );
         
+        // The voted tokens stay in escrow until voting ends, so they cannot be passed to
+        // another wallet and voted again
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.voter_claim_token_account.to_account_info(),
+            mint: ctx.accounts.claim_mint.to_account_info(),
+            to: ctx.accounts.vote_escrow.to_account_info(),
+            authority: voter.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token_interface::transfer_checked(cpi_ctx, escrow_amount, ctx.accounts.claim_mint.decimals)?;
+        
         let vote = &mut ctx.accounts.outcome_vote;
         vote.market = market.key();
         vote.voter = voter.key();
         vote.outcome_index = outcome_index;
         vote.bump = ctx.bumps.outcome_vote;
         
-        vote_result.vote_tallies[outcome_index as usize] = 
-            vote_result.vote_tallies[outcome_index as usize].checked_add(1).unwrap();
+        vote_result.record_vote(outcome_index, market.outcomes.len(), weight)?;
+        
+        msg!("Stake-weighted vote recorded for outcome {} with weight {}", outcome_index, weight);
+        Ok(())
+    }
+
+    pub fn reclaim_vote_tokens(ctx: Context<ReclaimVoteTokens>) -> Result<()> {
+        let market = &ctx.accounts.market;
+        
+        // Escrowed tokens can no longer sway the tally once voting closes or the market resolves
+        let voting_deadline = market.deadline.checked_add(15 * 24 * 60 * 60).unwrap();
+        require!(
+            market.resolved || Clock::get()?.unix_timestamp > voting_deadline,
+            ErrorCode::VotingNotEnded
+        );
+        
+        let index_bytes = market.market_index.to_le_bytes();
+        let seeds = market.signer_seeds(&index_bytes);
+        let signer = &[&seeds[..]];
+        let amount = ctx.accounts.vote_escrow.amount;
+        
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.vote_escrow.to_account_info(),
+            mint: ctx.accounts.claim_mint.to_account_info(),
+            to: ctx.accounts.voter_claim_token_account.to_account_info(),
+            authority: ctx.accounts.market.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.claim_mint.decimals)?;
+        
+        let cpi_accounts = token_interface::CloseAccount {
+            account: ctx.accounts.vote_escrow.to_account_info(),
+            destination: ctx.accounts.voter.to_account_info(),
+            authority: ctx.accounts.market.to_account_info(),
+        };
         
-        vote_result.stake_weights[outcome_index as usize] = 
-            vote_result.stake_weights[outcome_index as usize].checked_add(prediction.amount).unwrap();
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         
-        vote_result.vote_count = vote_result.vote_count.checked_add(1).unwrap();
+        token_interface::close_account(cpi_ctx)?;
         
-        msg!("Stake-weighted vote recorded for outcome {} with weight {}", outcome_index, prediction.amount);
+        msg!("Returned {} vote tokens to {}", amount, ctx.accounts.voter.key());
         Ok(())
     }
 
@@ -713,7 +1356,7 @@ pub mod contracts {
         );
         
         require!(
-            (outcome_index as usize) < market.outcomes.len(),
+            (outcome_index as usize) < market.outcomes.len() || outcome_index == INVALID_OUTCOME,
             ErrorCode::InvalidOutcomeIndex
         );
         
@@ -762,233 +1405,2456 @@ pub mod contracts {
         Ok(())
     }
 
-    pub fn initialize_protocol_stats(ctx: Context<InitializeProtocolStats>) -> Result<()> {
-        // Validate that only the authorized admin can initialize protocol stats
-        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+    pub fn initialize_outcome_mint(ctx: Context<InitializeOutcomeMint>, outcome_index: u8) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let i = outcome_index as usize;
         
-        let stats = &mut ctx.accounts.protocol_stats;
+        require!(i < market.outcomes.len(), ErrorCode::InvalidOutcomeIndex);
+        require!(
+            market.outcome_mints[i] == Pubkey::default(),
+            ErrorCode::OutcomeMintAlreadyInitialized
+        );
         
-        stats.total_volume = 0;
-        stats.total_markets = 0;
-        stats.total_users = 0;
-        stats.total_stakes = 0;
-        stats.resolved_markets = Vec::new();
-        stats.last_updated_ts = Clock::get()?.unix_timestamp;
-        stats.bump = ctx.bumps.protocol_stats;
+        market.outcome_mints[i] = ctx.accounts.outcome_mint.key();
         
-        msg!("Protocol stats initialized");
+        emit!(OutcomeMintInitializedEvent {
+            market: market.key(),
+            outcome_index,
+            mint: ctx.accounts.outcome_mint.key(),
+        });
+        
+        msg!("Outcome {} mint initialized: {}", outcome_index, ctx.accounts.outcome_mint.key());
         Ok(())
     }
 
-    pub fn initialize_user_profile(ctx: Context<InitializeUserProfile>) -> Result<()> {
-        let profile = &mut ctx.accounts.user_profile;
-        let user = ctx.accounts.user.key();
-
-        profile.user = user;
-        profile.total_staked = 0;
-        profile.total_winnings = 0;
-        profile.total_predictions = 0;
-        profile.winning_predictions = 0;
-        profile.last_active_ts = Clock::get()?.unix_timestamp;
-        profile.bump = ctx.bumps.user_profile;
-
-        msg!("User profile created for {}", user);
+    pub fn open_position(ctx: Context<OpenPosition>) -> Result<()> {
+        let position = &mut ctx.accounts.position;
+        
+        position.market = ctx.accounts.market.key();
+        position.owner = ctx.accounts.owner.key();
+        position.shares = vec![0; ctx.accounts.market.outcomes.len()];
+        position.lp_shares = 0;
+        position.bump = ctx.bumps.position;
+        
+        msg!("Share position opened for {}", position.owner);
         Ok(())
     }
 
-    pub fn update_creator_tier(ctx: Context<UpdateCreatorTier>) -> Result<()> {
-        // Validate that only the authorized admin can update creator tiers
-        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+    pub fn add_liquidity(
+        ctx: Context<TradeShares>,
+        amount: u64,
+        min_lp_shares: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
         
-        let creator_profile = &mut ctx.accounts.creator_profile;
-        let current_tier = creator_profile.tier;
+        require!(
+            market.trading_mode == TradingMode::Cpmm as u8,
+            ErrorCode::NotAmmMarket
+        );
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(amount > 0, ErrorCode::InvalidAmount);
         
-        // Tier promotion logic based on activity and volume
-        // Tier 0: Beginners (1.5% fee)
-        // Tier 1: Rising (5+ markets, 1000+ volume) (1.75% fee)
-        // Tier 2: Established (20+ markets, 10,000+ volume) (2% fee)
-        // Tier 3: Expert (50+ markets, 50,000+ volume, high traction) (3% fee)
-        // Tier 4: Elite (100+ markets, 200,000+ volume, very high traction) (4% fee)
-        // Tier 5: Master (200+ markets, 500,000+ volume, exceptional traction) (5% fee)
-        // This is just for the hackathon, we will change this to a more complex tier system in the future
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(market.deadline > current_time, ErrorCode::MarketExpired);
         
-        let new_tier = if creator_profile.markets_created >= 200 && creator_profile.total_volume >= 500_000 && creator_profile.traction_score >= 2000 {
-            5
-        } else if creator_profile.markets_created >= 100 && creator_profile.total_volume >= 200_000 && creator_profile.traction_score >= 1000 {
-            4
-        } else if creator_profile.markets_created >= 50 && creator_profile.total_volume >= 50_000 && creator_profile.traction_score >= 500 {
-            3
-        } else if creator_profile.markets_created >= 20 && creator_profile.total_volume >= 10_000 {
-            2
-        } else if creator_profile.markets_created >= 5 && creator_profile.total_volume >= 1_000 {
-            1
+        // The first deposit seeds an even pool; later deposits keep the current
+        // prices and hand back the shares the pool does not need as outcome shares
+        let max_reserve = market.amm_reserves.iter().copied().max().unwrap_or(0);
+        let lp_shares = if market.lp_total_shares == 0 || max_reserve == 0 {
+            for reserve in market.amm_reserves.iter_mut() {
+                *reserve = reserve.checked_add(amount).unwrap();
+            }
+            amount
         } else {
-            0
+            for j in 0..market.amm_reserves.len() {
+                let added = ((amount as u128) * (market.amm_reserves[j] as u128) / (max_reserve as u128)) as u64;
+                let send_back = amount.checked_sub(added).unwrap();
+                
+                market.amm_reserves[j] = market.amm_reserves[j].checked_add(added).unwrap();
+                position.shares[j] = position.shares[j].checked_add(send_back).unwrap();
+                market.outstanding_shares[j] = market.outstanding_shares[j].checked_add(send_back).unwrap();
+            }
+            ((amount as u128) * (market.lp_total_shares as u128) / (max_reserve as u128)) as u64
         };
         
-        if new_tier != current_tier {
-            creator_profile.tier = new_tier;
-            
+        require!(lp_shares > 0 && lp_shares >= min_lp_shares, ErrorCode::SlippageExceeded);
+        
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.market_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token::transfer(cpi_ctx, amount)?;
+        
+        market.total_pool = market.total_pool.checked_add(amount).unwrap();
+        market.lp_total_shares = market.lp_total_shares.checked_add(lp_shares).unwrap();
+        position.lp_shares = position.lp_shares.checked_add(lp_shares).unwrap();
+        
+        emit!(LiquidityChangedEvent {
+            provider: ctx.accounts.user.key(),
+            market: market.key(),
+            is_deposit: true,
+            collateral_amount: amount,
+            lp_shares,
+            lp_total_shares: market.lp_total_shares,
+        });
+        
+        msg!("Added {} liquidity for {} LP shares", amount, lp_shares);
+        Ok(())
+    }
+
+    pub fn remove_liquidity(ctx: Context<TradeShares>, lp_shares: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        
+        require!(
+            market.trading_mode == TradingMode::Cpmm as u8,
+            ErrorCode::NotAmmMarket
+        );
+        require!(lp_shares > 0, ErrorCode::InvalidAmount);
+        require!(position.lp_shares >= lp_shares, ErrorCode::InsufficientLpShares);
+        
+        // Pool shares go to the provider's position; collected fees are paid out
+        let lp_total = market.lp_total_shares as u128;
+        for j in 0..market.amm_reserves.len() {
+            let out = ((market.amm_reserves[j] as u128) * (lp_shares as u128) / lp_total) as u64;
             
-            msg!("Creator tier updated from {} to {}", current_tier, new_tier);
-            msg!("New creator fee: {}bps", get_creator_fee_bps(new_tier));
-        } else {
-            msg!("Creator tier remains at {}", current_tier);
+            market.amm_reserves[j] = market.amm_reserves[j].checked_sub(out).unwrap();
+            position.shares[j] = position.shares[j].checked_add(out).unwrap();
+            market.outstanding_shares[j] = market.outstanding_shares[j].checked_add(out).unwrap();
+        }
+        
+        let fees_out = ((market.lp_fees_accrued as u128) * (lp_shares as u128) / lp_total) as u64;
+        
+        market.lp_fees_accrued = market.lp_fees_accrued.checked_sub(fees_out).unwrap();
+        market.lp_total_shares = market.lp_total_shares.checked_sub(lp_shares).unwrap();
+        position.lp_shares = position.lp_shares.checked_sub(lp_shares).unwrap();
+        
+        if fees_out > 0 {
+            pay_from_vault(
+                market,
+                &ctx.accounts.market_vault,
+                ctx.accounts.user_token_account.to_account_info(),
+                &ctx.accounts.token_program,
+                fees_out,
+            )?;
         }
         
+        emit!(LiquidityChangedEvent {
+            provider: ctx.accounts.user.key(),
+            market: market.key(),
+            is_deposit: false,
+            collateral_amount: fees_out,
+            lp_shares,
+            lp_total_shares: market.lp_total_shares,
+        });
+        
+        msg!("Removed {} LP shares, {} in fees paid", lp_shares, fees_out);
         Ok(())
     }
 
-    pub fn update_protocol_stats(ctx: Context<UpdateProtocolStats>) -> Result<()> {
-        // Validate that only the authorized admin can update protocol stats
-        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+    pub fn buy_shares(
+        ctx: Context<TradeShares>,
+        outcome_index: u8,
+        amount: u64,
+        min_shares_out: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        let i = outcome_index as usize;
         
-        let stats = &mut ctx.accounts.protocol_stats;
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(i < market.outcomes.len(), ErrorCode::InvalidOutcomeIndex);
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(market.deadline > current_time, ErrorCode::MarketExpired);
+        
+        let (shares_out, fee) = market.apply_buy(i, amount)?;
+        require!(
+            shares_out > 0 && shares_out >= min_shares_out,
+            ErrorCode::SlippageExceeded
+        );
+        
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.market_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token::transfer(cpi_ctx, amount)?;
+        
+        position.shares[i] = position.shares[i].checked_add(shares_out).unwrap();
+        
+        emit!(SharesTradedEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            outcome_index,
+            is_buy: true,
+            collateral_amount: amount,
+            shares: shares_out,
+            fee,
+        });
+        
+        msg!("Bought {} shares of outcome {} for {}", shares_out, outcome_index, amount);
+        Ok(())
+    }
 
-        if let Some(_markets) = &ctx.accounts.markets {
-            stats.total_markets = stats.total_markets.checked_add(1).unwrap();
+    pub fn sell_shares(
+        ctx: Context<TradeShares>,
+        outcome_index: u8,
+        return_amount: u64,
+        max_shares_in: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        let i = outcome_index as usize;
+        
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(i < market.outcomes.len(), ErrorCode::InvalidOutcomeIndex);
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(return_amount > 0, ErrorCode::InvalidAmount);
+        
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(market.deadline > current_time, ErrorCode::MarketExpired);
+        
+        let (shares_in, fee) = market.apply_sell(i, return_amount)?;
+        require!(shares_in <= max_shares_in, ErrorCode::SlippageExceeded);
+        require!(position.shares[i] >= shares_in, ErrorCode::InsufficientShares);
+        
+        position.shares[i] = position.shares[i].checked_sub(shares_in).unwrap();
+        
+        pay_from_vault(
+            market,
+            &ctx.accounts.market_vault,
+            ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            return_amount,
+        )?;
+        
+        emit!(SharesTradedEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            outcome_index,
+            is_buy: false,
+            collateral_amount: return_amount,
+            shares: shares_in,
+            fee,
+        });
+        
+        msg!("Sold {} shares of outcome {} for {}", shares_in, outcome_index, return_amount);
+        Ok(())
+    }
+
+    // Current outcome prices scaled by PRICE_SCALE, read with a simulated call
+    pub fn quote_prices(ctx: Context<QuotePrices>) -> Result<Vec<u64>> {
+        ctx.accounts.market.share_prices()
+    }
+
+    pub fn mint_complete_set(ctx: Context<TradeShares>, amount: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.user_token_account.to_account_info(),
+            to: ctx.accounts.market_vault.to_account_info(),
+            authority: ctx.accounts.user.to_account_info(),
+        };
+        
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+        
+        token::transfer(cpi_ctx, amount)?;
+        
+        market.mint_complete_sets(amount);
+        for shares in position.shares.iter_mut() {
+            *shares = shares.checked_add(amount).unwrap();
         }
         
-        if let Some(prediction) = &ctx.accounts.prediction {
-            stats.total_volume = stats.total_volume.checked_add(prediction.amount).unwrap();
-            stats.total_stakes = stats.total_stakes.checked_add(1).unwrap();
+        emit!(CompleteSetEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            is_mint: true,
+            amount,
+            total_pool: market.total_pool,
+        });
+        
+        msg!("Minted {} complete sets", amount);
+        Ok(())
+    }
+
+    pub fn redeem_complete_set(ctx: Context<TradeShares>, amount: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(
+            position.shares.iter().all(|shares| *shares >= amount),
+            ErrorCode::InsufficientShares
+        );
+        
+        market.burn_complete_sets(amount)?;
+        for shares in position.shares.iter_mut() {
+            *shares = shares.checked_sub(amount).unwrap();
         }
-                
-        if let Some(market) = &ctx.accounts.market {
-            if market.resolved && !stats.resolved_markets.contains(&market.key()) {
-                // Keep only the 20 most recent resolved markets
-                if stats.resolved_markets.len() >= 20 {
-                    stats.resolved_markets.remove(0);
-                }
-                stats.resolved_markets.push(market.key());
+        
+        pay_from_vault(
+            market,
+            &ctx.accounts.market_vault,
+            ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        
+        emit!(CompleteSetEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            is_mint: false,
+            amount,
+            total_pool: market.total_pool,
+        });
+        
+        msg!("Redeemed {} complete sets", amount);
+        Ok(())
+    }
+
+    pub fn initialize_order_book(ctx: Context<InitializeOrderBook>, outcome_index: u8) -> Result<()> {
+        let market = &ctx.accounts.market;
+        
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(
+            (outcome_index as usize) < market.outcomes.len(),
+            ErrorCode::InvalidOutcomeIndex
+        );
+        
+        let order_book = &mut ctx.accounts.order_book;
+        order_book.market = market.key();
+        order_book.outcome_index = outcome_index;
+        order_book.next_order_id = 0;
+        order_book.bids = Vec::new();
+        order_book.asks = Vec::new();
+        order_book.events = Vec::new();
+        order_book.bump = ctx.bumps.order_book;
+        
+        msg!("Order book initialized for outcome {}", outcome_index);
+        Ok(())
+    }
+
+    pub fn place_order(
+        ctx: Context<TradeOrderBook>,
+        side: u8,
+        price: u64,
+        quantity: u64,
+    ) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        let position = &mut ctx.accounts.position;
+        let i = order_book.outcome_index as usize;
+        
+        require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+        require!(
+            side <= OrderSide::Ask as u8 && price > 0 && price < PRICE_SCALE && quantity > 0,
+            ErrorCode::InvalidOrder
+        );
+        
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(market.deadline > current_time, ErrorCode::MarketExpired);
+        
+        let fill = order_book.match_order(side, price, quantity)?;
+        let resting = quantity.checked_sub(fill.filled).unwrap();
+        let order_id = order_book.next_order_id;
+        
+        if resting > 0 {
+            order_book.insert_order(side, Order {
+                order_id,
+                position: position.key(),
+                price,
+                quantity: resting,
+            })?;
+        }
+        order_book.next_order_id = order_id.checked_add(1).unwrap();
+        
+        if side == OrderSide::Bid as u8 {
+            // Pay for the fills and escrow the resting bid in one transfer
+            let escrow = order_collateral(resting, price);
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.user_token_account.to_account_info(),
+                to: ctx.accounts.market_vault.to_account_info(),
+                authority: ctx.accounts.user.to_account_info(),
+            };
+            
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
+            
+            token::transfer(cpi_ctx, fill.taker_collateral.checked_add(escrow).unwrap())?;
+            
+            market.clob_escrow = market
+                .clob_escrow
+                .checked_add(fill.maker_collateral)
+                .unwrap()
+                .checked_add(escrow)
+                .unwrap();
+            position.shares[i] = position.shares[i].checked_add(fill.filled).unwrap();
+        } else {
+            require!(position.shares[i] >= quantity, ErrorCode::InsufficientShares);
+            
+            position.shares[i] = position.shares[i].checked_sub(quantity).unwrap();
+            market.clob_escrow = market.clob_escrow.checked_sub(fill.maker_collateral).unwrap();
+            
+            if fill.taker_collateral > 0 {
+                pay_from_vault(
+                    market,
+                    &ctx.accounts.market_vault,
+                    ctx.accounts.user_token_account.to_account_info(),
+                    &ctx.accounts.token_program,
+                    fill.taker_collateral,
+                )?;
             }
         }
         
-        stats.last_updated_ts = Clock::get()?.unix_timestamp;
-        msg!("Protocol stats updated");
+        emit!(OrderPlacedEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            outcome_index: order_book.outcome_index,
+            order_id,
+            side,
+            price,
+            filled: fill.filled,
+            resting,
+        });
+        
+        msg!("Order {} placed: {} filled, {} resting", order_id, fill.filled, resting);
         Ok(())
     }
-}
+
+    pub fn cancel_order(ctx: Context<TradeOrderBook>, order_id: u64) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let order_book = &mut ctx.accounts.order_book;
+        let position = &mut ctx.accounts.position;
+        let i = order_book.outcome_index as usize;
+        
+        let (side, order) = order_book.remove_order(order_id, &position.key())?;
+        
+        if side == OrderSide::Bid as u8 {
+            let refund = order_collateral(order.quantity, order.price);
+            market.clob_escrow = market.clob_escrow.checked_sub(refund).unwrap();
+            
+            if refund > 0 {
+                pay_from_vault(
+                    market,
+                    &ctx.accounts.market_vault,
+                    ctx.accounts.user_token_account.to_account_info(),
+                    &ctx.accounts.token_program,
+                    refund,
+                )?;
+            }
+        } else {
+            position.shares[i] = position.shares[i].checked_add(order.quantity).unwrap();
+        }
+        
+        emit!(OrderCancelledEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            outcome_index: order_book.outcome_index,
+            order_id,
+            remaining: order.quantity,
+        });
+        
+        msg!("Order {} cancelled with {} remaining", order_id, order.quantity);
+        Ok(())
+    }
+
+    // Permissionless crank: credits makers for queued fills. The maker positions
+    // of the events being consumed are passed, writable, as remaining accounts.
+    pub fn consume_events<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ConsumeEvents<'info>>,
+        limit: u8,
+    ) -> Result<()> {
+        let market_key = ctx.accounts.market.key();
+        let order_book = &mut ctx.accounts.order_book;
+        let i = order_book.outcome_index as usize;
+        let mut consumed = 0;
+        
+        while consumed < limit as usize && !order_book.events.is_empty() {
+            let event = order_book.events[0].clone();
+            let Some(info) = ctx
+                .remaining_accounts
+                .iter()
+                .find(|info| info.key() == event.maker_position)
+            else {
+                break;
+            };
+            
+            let mut maker: Account<OutcomePosition> = Account::try_from(info)?;
+            require_keys_eq!(maker.market, market_key, ErrorCode::InvalidOrder);
+            
+            // Filled bids receive shares and filled asks collateral; evictions return the escrow
+            if (event.maker_side == OrderSide::Bid as u8) != event.evicted {
+                maker.shares[i] = maker.shares[i].checked_add(event.quantity).unwrap();
+            } else {
+                maker.free_collateral = maker.free_collateral.checked_add(event.collateral).unwrap();
+            }
+            maker.exit(&crate::ID)?;
+            
+            order_book.events.remove(0);
+            consumed += 1;
+        }
+        
+        msg!("Consumed {} fill events, {} left", consumed, order_book.events.len());
+        Ok(())
+    }
+
+    pub fn settle_funds(ctx: Context<TradeShares>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        let amount = position.free_collateral;
+        
+        require!(amount > 0, ErrorCode::NoFundsToSettle);
+        
+        position.free_collateral = 0;
+        market.clob_escrow = market.clob_escrow.checked_sub(amount).unwrap();
+        
+        pay_from_vault(
+            market,
+            &ctx.accounts.market_vault,
+            ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            amount,
+        )?;
+        
+        msg!("Settled {} from filled orders", amount);
+        Ok(())
+    }
+
+    pub fn redeem_shares(ctx: Context<TradeShares>) -> Result<()> {
+        let market = &mut ctx.accounts.market;
+        let position = &mut ctx.accounts.position;
+        
+        require!(market.is_share_market(), ErrorCode::NotAmmMarket);
+        require!(market.resolved, ErrorCode::MarketNotResolved);
+        require!(!market.swept, ErrorCode::MarketVaultSwept);
+        
+        let payout = market.share_payout(&position.shares)?;
+        require!(payout > 0, ErrorCode::NoSharesToRedeem);
+        
+        for (j, shares) in position.shares.iter_mut().enumerate() {
+            market.outstanding_shares[j] = market.outstanding_shares[j].checked_sub(*shares).unwrap();
+            *shares = 0;
+        }
+        market.total_claimed = market.total_claimed.checked_add(payout).unwrap();
+        
+        pay_from_vault(
+            market,
+            &ctx.accounts.market_vault,
+            ctx.accounts.user_token_account.to_account_info(),
+            &ctx.accounts.token_program,
+            payout,
+        )?;
+        
+        emit!(SharesRedeemedEvent {
+            user: ctx.accounts.user.key(),
+            market: market.key(),
+            payout,
+        });
+        
+        msg!("Redeemed shares for {}", payout);
+        Ok(())
+    }
+
+    pub fn initialize_treasury(ctx: Context<InitializeTreasury>) -> Result<()> {
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+
+        emit!(TreasuryInitializedEvent {
+            treasury: ctx.accounts.treasury.key(),
+            mint: ctx.accounts.mint.key(),
+        });
+
+        msg!("Protocol treasury initialized for mint {}", ctx.accounts.mint.key());
+        Ok(())
+    }
+
+    pub fn withdraw_treasury(ctx: Context<WithdrawTreasury>, amount: u64) -> Result<()> {
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        require!(amount > 0, ErrorCode::InvalidAmount);
+        require!(
+            ctx.accounts.treasury.amount >= amount,
+            ErrorCode::InsufficientTreasuryBalance
+        );
+
+        let seeds = &[
+            b"treasury_authority".as_ref(),
+            &[ctx.bumps.treasury_authority],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = TransferChecked {
+            from: ctx.accounts.treasury.to_account_info(),
+            mint: ctx.accounts.mint.to_account_info(),
+            to: ctx.accounts.destination.to_account_info(),
+            authority: ctx.accounts.treasury_authority.to_account_info(),
+        };
+
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+
+        token_interface::transfer_checked(cpi_ctx, amount, ctx.accounts.mint.decimals)?;
+
+        ctx.accounts.treasury.reload()?;
+
+        emit!(TreasuryWithdrawnEvent {
+            treasury: ctx.accounts.treasury.key(),
+            mint: ctx.accounts.treasury.mint,
+            destination: ctx.accounts.destination.key(),
+            amount,
+            remaining: ctx.accounts.treasury.amount,
+        });
+
+        msg!("Withdrew {} from protocol treasury", amount);
+        Ok(())
+    }
+
+    // Markets can only be created in mints the admin has registered and left enabled
+    pub fn register_collateral_mint(
+        ctx: Context<RegisterCollateralMint>,
+        min_stake: u64,
+    ) -> Result<()> {
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let collateral_config = &mut ctx.accounts.collateral_config;
+        collateral_config.mint = ctx.accounts.mint.key();
+        collateral_config.decimals = ctx.accounts.mint.decimals;
+        collateral_config.min_stake = min_stake;
+        collateral_config.enabled = true;
+        collateral_config.bump = ctx.bumps.collateral_config;
+        
+        emit!(CollateralMintUpdatedEvent {
+            mint: collateral_config.mint,
+            decimals: collateral_config.decimals,
+            min_stake,
+            enabled: true,
+        });
+        
+        msg!("Collateral mint registered: {}", collateral_config.mint);
+        Ok(())
+    }
+
+    // Disabling a mint stops new markets in it; existing markets keep their settings
+    pub fn update_collateral_mint(
+        ctx: Context<UpdateCollateralMint>,
+        min_stake: u64,
+        enabled: bool,
+    ) -> Result<()> {
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let collateral_config = &mut ctx.accounts.collateral_config;
+        collateral_config.min_stake = min_stake;
+        collateral_config.enabled = enabled;
+        
+        emit!(CollateralMintUpdatedEvent {
+            mint: collateral_config.mint,
+            decimals: collateral_config.decimals,
+            min_stake,
+            enabled,
+        });
+        
+        msg!("Collateral mint {} updated (enabled: {})", collateral_config.mint, enabled);
+        Ok(())
+    }
+
+    pub fn initialize_protocol_stats(ctx: Context<InitializeProtocolStats>) -> Result<()> {
+        // Validate that only the authorized admin can initialize protocol stats
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let stats = &mut ctx.accounts.protocol_stats;
+        
+        stats.total_volume = 0;
+        stats.total_markets = 0;
+        stats.total_users = 0;
+        stats.total_stakes = 0;
+        stats.resolved_markets = Vec::new();
+        stats.last_updated_ts = Clock::get()?.unix_timestamp;
+        stats.bump = ctx.bumps.protocol_stats;
+        
+        msg!("Protocol stats initialized");
+        Ok(())
+    }
+
+    pub fn initialize_user_profile(ctx: Context<InitializeUserProfile>) -> Result<()> {
+        let profile = &mut ctx.accounts.user_profile;
+        let user = ctx.accounts.user.key();
+
+        profile.user = user;
+        profile.total_staked = 0;
+        profile.total_winnings = 0;
+        profile.total_predictions = 0;
+        profile.winning_predictions = 0;
+        profile.last_active_ts = Clock::get()?.unix_timestamp;
+        profile.bump = ctx.bumps.user_profile;
+
+        msg!("User profile created for {}", user);
+        Ok(())
+    }
+
+    pub fn update_creator_tier(ctx: Context<UpdateCreatorTier>) -> Result<()> {
+        // Validate that only the authorized admin can update creator tiers
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let creator_profile = &mut ctx.accounts.creator_profile;
+        let current_tier = creator_profile.tier;
+        
+        // Tier promotion logic based on activity and volume
+        // Tier 0: Beginners (1.5% fee)
+        // Tier 1: Rising (5+ markets, 1000+ volume) (1.75% fee)
+        // Tier 2: Established (20+ markets, 10,000+ volume) (2% fee)
+        // Tier 3: Expert (50+ markets, 50,000+ volume, high traction) (3% fee)
+        // Tier 4: Elite (100+ markets, 200,000+ volume, very high traction) (4% fee)
+        // Tier 5: Master (200+ markets, 500,000+ volume, exceptional traction) (5% fee)
+        // This is just for the hackathon, we will change this to a more complex tier system in the future
+        
+        let new_tier = if creator_profile.markets_created >= 200 && creator_profile.total_volume >= 500_000 && creator_profile.traction_score >= 2000 {
+            5
+        } else if creator_profile.markets_created >= 100 && creator_profile.total_volume >= 200_000 && creator_profile.traction_score >= 1000 {
+            4
+        } else if creator_profile.markets_created >= 50 && creator_profile.total_volume >= 50_000 && creator_profile.traction_score >= 500 {
+            3
+        } else if creator_profile.markets_created >= 20 && creator_profile.total_volume >= 10_000 {
+            2
+        } else if creator_profile.markets_created >= 5 && creator_profile.total_volume >= 1_000 {
+            1
+        } else {
+            0
+        };
+        
+        if new_tier != current_tier {
+            creator_profile.tier = new_tier;
+            
+            
+            msg!("Creator tier updated from {} to {}", current_tier, new_tier);
+            msg!("New creator fee: {}bps", get_creator_fee_bps(new_tier));
+        } else {
+            msg!("Creator tier remains at {}", current_tier);
+        }
+        
+        Ok(())
+    }
+
+    pub fn update_protocol_stats(ctx: Context<UpdateProtocolStats>) -> Result<()> {
+        // Validate that only the authorized admin can update protocol stats
+        require!(is_admin(&ctx.accounts.admin.key()), ErrorCode::Unauthorized);
+        
+        let stats = &mut ctx.accounts.protocol_stats;
+
+        if let Some(_markets) = &ctx.accounts.markets {
+            stats.total_markets = stats.total_markets.checked_add(1).unwrap();
+        }
+        
+        if let Some(prediction) = &ctx.accounts.prediction {
+            stats.total_volume = stats.total_volume.checked_add(prediction.amount).unwrap();
+            stats.total_stakes = stats.total_stakes.checked_add(1).unwrap();
+        }
+                
+        if let Some(market) = &ctx.accounts.market {
+            if market.resolved && !stats.resolved_markets.contains(&market.key()) {
+                // Keep only the 20 most recent resolved markets
+                if stats.resolved_markets.len() >= 20 {
+                    stats.resolved_markets.remove(0);
+                }
+                stats.resolved_markets.push(market.key());
+            }
+        }
+        
+        stats.last_updated_ts = Clock::get()?.unix_timestamp;
+        msg!("Protocol stats updated");
+        Ok(())
+    }
+}
+
+// Checks shared by every AI resolution: the resolver's authority, the deadline and the
+// confidence score. `set_payouts` checks the market type and records the result
+fn resolve_via_ai(
+    accounts: &mut ResolveMarketViaAI,
+    ai_confidence_score: f32,
+    set_payouts: impl FnOnce(&mut Market) -> Result<()>,
+) -> Result<()> {
+    let market = &mut accounts.market;
+    
+    require!(
+        accounts.ai_resolver.authority == accounts.resolver_authority.key(),
+        ErrorCode::Unauthorized
+    );
+    
+    require!(accounts.ai_resolver.active, ErrorCode::ResolverInactive);
+    
+    require!(!market.resolved, ErrorCode::MarketAlreadyResolved);
+    
+    let current_time = Clock::get()?.unix_timestamp;
+    require!(current_time >= market.deadline, ErrorCode::MarketNotExpired);
+    
+    require!(ai_confidence_score >= 0.85, ErrorCode::LowAIConfidence);
+    
+    set_payouts(market)?;
+    market.resolved = true;
+    market.resolved_at = current_time;
+    
+    accounts.ai_resolver.resolution_count = accounts.ai_resolver.resolution_count.checked_add(1).unwrap();
+    
+    Ok(())
+}
+
+// Shared by stake_prediction and the binary stake_yes / stake_no shortcuts
+fn process_stake(
+    ctx: Context<StakePrediction>,
+    outcome_index: u8,
+    amount: u64,
+    max_outcome_share_bps: Option<u16>,
+) -> Result<()> {
+    let accounts = ctx.accounts;
+    let stake = StakeAccounts {
+        market: &mut accounts.market,
+        creator_profile: &mut accounts.creator_profile,
+        prediction: &mut accounts.prediction,
+        prediction_bump: ctx.bumps.prediction,
+        user_profile: accounts.user_profile.as_mut(),
+        collateral: CollateralVault {
+            market_vault: &accounts.market_vault,
+            mint: &accounts.collateral_mint,
+            sol_vault: accounts.sol_vault.as_ref(),
+            token_program: &accounts.token_program,
+            system_program: Some(&accounts.system_program),
+        },
+        user_token_account: accounts.user_token_account.as_ref(),
+        token_authority: accounts.token_authority.to_account_info(),
+        beneficiary: accounts.beneficiary.key(),
+        outcome_mint: &accounts.outcome_mint,
+        user_outcome_token_account: &accounts.user_outcome_token_account,
+    };
+    
+    execute_stake(stake, outcome_index, amount, max_outcome_share_bps)
+}
+
+// Everything a single stake touches, borrowed from a StakePrediction context or
+// loaded from one batch_stake account group
+struct StakeAccounts<'a, 'info> {
+    market: &'a mut Account<'info, Market>,
+    creator_profile: &'a mut Account<'info, CreatorProfile>,
+    prediction: &'a mut Account<'info, Prediction>,
+    prediction_bump: u8,
+    user_profile: Option<&'a mut Account<'info, UserProfile>>,
+    collateral: CollateralVault<'a, 'info>,
+    user_token_account: Option<&'a InterfaceAccount<'info, token_interface::TokenAccount>>,
+    token_authority: AccountInfo<'info>,
+    beneficiary: Pubkey,
+    outcome_mint: &'a InterfaceAccount<'info, token_interface::Mint>,
+    user_outcome_token_account: &'a InterfaceAccount<'info, token_interface::TokenAccount>,
+}
+
+// Size limits and the optional slippage bound, checked by every way of staking
+fn check_stake_request(
+    market: &Market,
+    outcome_index: u8,
+    amount: u64,
+    max_outcome_share_bps: Option<u16>,
+) -> Result<()> {
+    market.check_stake(outcome_index, amount)?;
+    
+    if let Some(max_share_bps) = max_outcome_share_bps {
+        require!(
+            market.outcome_share_bps(outcome_index, amount) <= max_share_bps as u64,
+            ErrorCode::SlippageExceeded
+        );
+    }
+    
+    Ok(())
+}
+
+fn execute_stake(
+    stake: StakeAccounts,
+    outcome_index: u8,
+    amount: u64,
+    max_outcome_share_bps: Option<u16>,
+) -> Result<()> {
+    check_stake_request(stake.market, outcome_index, amount, max_outcome_share_bps)?;
+    stake.prediction.check_top_up(outcome_index)?;
+    
+    let collateral = &stake.collateral;
+    let source = collateral.account_for(
+        stake.market,
+        &stake.token_authority,
+        stake.user_token_account,
+    )?;
+    
+    let amount = collateral.deposit(stake.market, source, stake.token_authority, amount)?;
+    
+    let market = &stake.market;
+    
+    // Each unit staked is backed by one transferable outcome token
+    require!(
+        market.outcome_index_for_mint(&stake.outcome_mint.key())? == outcome_index,
+        ErrorCode::InvalidOutcomeMint
+    );
+    
+    {
+        let index_bytes = market.market_index.to_le_bytes();
+        let seeds = market.signer_seeds(&index_bytes);
+        let signer = &[&seeds[..]];
+        
+        let cpi_accounts = token_interface::MintTo {
+            mint: stake.outcome_mint.to_account_info(),
+            to: stake.user_outcome_token_account.to_account_info(),
+            authority: market.to_account_info(),
+        };
+        
+        let cpi_program = collateral.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        
+        token_interface::mint_to(cpi_ctx, amount)?;
+    }
+    
+    let prediction = stake.prediction;
+    prediction.user = stake.beneficiary;
+    prediction.position_mint = Pubkey::default();
+    prediction.bump = stake.prediction_bump;
+    
+    record_prediction(
+        stake.market,
+        prediction,
+        stake.creator_profile,
+        stake.user_profile,
+        outcome_index,
+        amount,
+    )
+}
+
+// One batch_stake account group, checked the way StakePrediction's constraints would be
+struct BatchGroup<'info> {
+    market: Account<'info, Market>,
+    creator_profile: Account<'info, CreatorProfile>,
+    prediction: &'info AccountInfo<'info>,
+    prediction_bump: u8,
+    market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    sol_vault: Option<SystemAccount<'info>>,
+    user_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    outcome_mint: InterfaceAccount<'info, token_interface::Mint>,
+    user_outcome_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+}
+
+// Every entry needs its own group of accounts, and a market can only be staked on once
+fn check_batch_entries(entries: &[BatchStakeEntry], remaining_accounts: &[AccountInfo]) -> Result<()> {
+    require!(
+        !entries.is_empty() && remaining_accounts.len() == entries.len() * BATCH_STAKE_ACCOUNTS,
+        ErrorCode::BatchAccountsMismatch
+    );
+    
+    for (i, entry) in entries.iter().enumerate() {
+        require!((entry.group as usize) < entries.len(), ErrorCode::BatchAccountsMismatch);
+        require!(
+            entries[..i].iter().all(|earlier| earlier.group != entry.group),
+            ErrorCode::DuplicateBatchMarket
+        );
+    }
+    
+    // Each group leads with its market, so two groups must not share one either
+    let markets: Vec<&Pubkey> = remaining_accounts.iter().step_by(BATCH_STAKE_ACCOUNTS).map(|info| info.key).collect();
+    for (i, market) in markets.iter().enumerate() {
+        require!(!markets[..i].contains(market), ErrorCode::DuplicateBatchMarket);
+    }
+    
+    Ok(())
+}
+
+// Loads an entry's group without touching any account, so every check runs before the stake
+fn load_batch_group<'info>(
+    entry: &BatchStakeEntry,
+    group: &'info [AccountInfo<'info>],
+    beneficiary_key: &Pubkey,
+    token_program_key: &Pubkey,
+) -> Result<BatchGroup<'info>> {
+    let [
+        market_info,
+        creator_profile_info,
+        prediction_info,
+        market_vault_info,
+        collateral_mint_info,
+        collateral_info,
+        outcome_mint_info,
+        user_outcome_info,
+    ] = group
+    else {
+        return err!(ErrorCode::BatchAccountsMismatch);
+    };
+    
+    let market: Account<Market> = Account::try_from(market_info)?;
+    let market_key = market.key();
+    
+    require_batch_pda(creator_profile_info, &[b"creator_profile", market.creator.as_ref()])?;
+    let creator_profile: Account<CreatorProfile> = Account::try_from(creator_profile_info)?;
+    
+    require_batch_pda(market_vault_info, &[b"market_vault", market_key.as_ref()])?;
+    require_keys_eq!(*market_vault_info.owner, *token_program_key, ErrorCode::BatchAccountsMismatch);
+    let market_vault: InterfaceAccount<token_interface::TokenAccount> =
+        InterfaceAccount::try_from(market_vault_info)?;
+    
+    require_keys_eq!(collateral_mint_info.key(), market_vault.mint, ErrorCode::BatchAccountsMismatch);
+    let collateral_mint: InterfaceAccount<token_interface::Mint> =
+        InterfaceAccount::try_from(collateral_mint_info)?;
+    
+    // Native markets take the SOL vault in the slot SPL markets use for the user's token account
+    let (sol_vault, user_token_account) = if market.native_sol {
+        require_batch_pda(collateral_info, &[b"sol_vault", market_key.as_ref()])?;
+        (Some(SystemAccount::try_from(collateral_info)?), None)
+    } else {
+        let user_token_account: InterfaceAccount<token_interface::TokenAccount> =
+            InterfaceAccount::try_from(collateral_info)?;
+        require_keys_eq!(user_token_account.mint, market_vault.mint, ErrorCode::BatchAccountsMismatch);
+        (None, Some(user_token_account))
+    };
+    
+    require_keys_eq!(*outcome_mint_info.owner, *token_program_key, ErrorCode::BatchAccountsMismatch);
+    let outcome_mint: InterfaceAccount<token_interface::Mint> =
+        InterfaceAccount::try_from(outcome_mint_info)?;
+    
+    let user_outcome_token_account: InterfaceAccount<token_interface::TokenAccount> =
+        InterfaceAccount::try_from(user_outcome_info)?;
+    require!(
+        user_outcome_token_account.owner == *beneficiary_key
+            && user_outcome_token_account.mint == outcome_mint.key(),
+        ErrorCode::BatchAccountsMismatch
+    );
+    
+    let prediction_bump = require_batch_pda(
+        prediction_info,
+        &[b"prediction", market_key.as_ref(), beneficiary_key.as_ref()],
+    )?;
+    
+    // A prediction the program already owns is topped up. Anything else must be a fresh or
+    // pre-funded address, and create_prediction_account keeps those lamports
+    if *prediction_info.owner == crate::ID {
+        let prediction: Account<Prediction> = Account::try_from(prediction_info)?;
+        prediction.check_top_up(entry.outcome_index)?;
+    } else {
+        require_keys_eq!(*prediction_info.owner, System::id(), ErrorCode::BatchAccountsMismatch);
+    }
+    
+    Ok(BatchGroup {
+        market,
+        creator_profile,
+        prediction: prediction_info,
+        prediction_bump,
+        market_vault,
+        collateral_mint,
+        sol_vault,
+        user_token_account,
+        outcome_mint,
+        user_outcome_token_account,
+    })
+}
+
+// Stakes one batch entry through the same path as stake_prediction
+fn stake_batch_entry<'info>(
+    accounts: &mut BatchStake<'info>,
+    entry: &BatchStakeEntry,
+    group: &'info [AccountInfo<'info>],
+) -> Result<()> {
+    let beneficiary_key = accounts.beneficiary.key();
+    let BatchGroup {
+        mut market,
+        mut creator_profile,
+        prediction: prediction_info,
+        prediction_bump,
+        market_vault,
+        collateral_mint,
+        sol_vault,
+        user_token_account,
+        outcome_mint,
+        user_outcome_token_account,
+    } = load_batch_group(entry, group, &beneficiary_key, &accounts.token_program.key())?;
+    
+    let market_key = market.key();
+    let mut prediction: Account<Prediction> = if *prediction_info.owner == crate::ID {
+        Account::try_from(prediction_info)?
+    } else {
+        create_prediction_account(
+            &accounts.payer,
+            prediction_info,
+            &accounts.system_program,
+            &[b"prediction", market_key.as_ref(), beneficiary_key.as_ref(), &[prediction_bump]],
+        )?;
+        Account::try_from_unchecked(prediction_info)?
+    };
+    
+    let stake = StakeAccounts {
+        market: &mut market,
+        creator_profile: &mut creator_profile,
+        prediction: &mut prediction,
+        prediction_bump,
+        user_profile: accounts.user_profile.as_mut(),
+        collateral: CollateralVault {
+            market_vault: &market_vault,
+            mint: &collateral_mint,
+            sol_vault: sol_vault.as_ref(),
+            token_program: &accounts.token_program,
+            system_program: Some(&accounts.system_program),
+        },
+        user_token_account: user_token_account.as_ref(),
+        token_authority: accounts.token_authority.to_account_info(),
+        beneficiary: beneficiary_key,
+        outcome_mint: &outcome_mint,
+        user_outcome_token_account: &user_outcome_token_account,
+    };
+    
+    execute_stake(stake, entry.outcome_index, entry.amount, entry.max_outcome_share_bps)?;
+    
+    // Anchor only writes back accounts named in the context, and a later entry may
+    // load the same creator profile
+    market.exit(&crate::ID)?;
+    creator_profile.exit(&crate::ID)?;
+    prediction.exit(&crate::ID)
+}
+
+// A batch_stake account must sit at the PDA StakePrediction would derive for it
+fn require_batch_pda(info: &AccountInfo, seeds: &[&[u8]]) -> Result<u8> {
+    let (address, bump) = Pubkey::find_program_address(seeds, &crate::ID);
+    require_keys_eq!(info.key(), address, ErrorCode::BatchAccountsMismatch);
+    Ok(bump)
+}
+
+// What `init` does for the prediction PDA, including when the address was pre-funded.
+// load_batch_group has already checked nothing else owns it
+fn create_prediction_account<'info>(
+    payer: &Signer<'info>,
+    prediction: &AccountInfo<'info>,
+    system_program: &Program<'info, System>,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let space = 8 + Prediction::SPACE;
+    let rent = Rent::get()?.minimum_balance(space);
+    let signer = &[signer_seeds];
+    
+    if prediction.lamports() == 0 {
+        let cpi_accounts = system_program::CreateAccount {
+            from: payer.to_account_info(),
+            to: prediction.clone(),
+        };
+        
+        let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+        
+        return system_program::create_account(cpi_ctx, rent, space as u64, &crate::ID);
+    }
+    
+    let top_up = rent.saturating_sub(prediction.lamports());
+    if top_up > 0 {
+        let cpi_accounts = system_program::Transfer {
+            from: payer.to_account_info(),
+            to: prediction.clone(),
+        };
+        
+        system_program::transfer(CpiContext::new(system_program.to_account_info(), cpi_accounts), top_up)?;
+    }
+    
+    let cpi_accounts = system_program::Allocate {
+        account_to_allocate: prediction.clone(),
+    };
+    system_program::allocate(
+        CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer),
+        space as u64,
+    )?;
+    
+    let cpi_accounts = system_program::Assign {
+        account_to_assign: prediction.clone(),
+    };
+    system_program::assign(
+        CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer),
+        &crate::ID,
+    )
+}
+
+// Books a stake on the market, the prediction record and the profiles
+fn record_prediction(
+    market: &mut Account<Market>,
+    prediction: &mut Account<Prediction>,
+    creator_profile: &mut Account<CreatorProfile>,
+    user_profile: Option<&mut Account<UserProfile>>,
+    outcome_index: u8,
+    amount: u64,
+) -> Result<()> {
+    let current_time = Clock::get()?.unix_timestamp;
+    
+    // A wallet has one prediction per market and tops it up on later stakes, so its amount
+    // is all the wallet has staked there, however many outcome token accounts it mints into
+    let new_prediction = prediction.amount == 0;
+    let position = prediction.amount.checked_add(amount).unwrap();
+    market.check_position(position)?;
+    
+    prediction.market = market.key();
+    prediction.outcome_index = outcome_index;
+    prediction.amount = position;
+    prediction.timestamp = current_time;
+    
+    market.record_stake(outcome_index, amount);
+    
+    creator_profile.total_volume = creator_profile.total_volume.checked_add(amount).unwrap();
+    
+    creator_profile.traction_score = creator_profile.traction_score.checked_add(amount / 1000 + 1).unwrap();
+    
+    if let Some(user_profile) = user_profile {
+        user_profile.total_staked = user_profile.total_staked.checked_add(amount).unwrap();
+        if new_prediction {
+            user_profile.total_predictions = user_profile.total_predictions.checked_add(1).unwrap();
+        }
+        user_profile.last_active_ts = current_time;
+    }
+    
+    let previous_tier = creator_profile.tier;
+    if let Some(creator_tier_threshold) = get_next_tier_threshold(creator_profile) {
+        if creator_profile.total_volume >= creator_tier_threshold.0 && 
+           creator_profile.markets_created >= creator_tier_threshold.1 && 
+           creator_profile.traction_score >= creator_tier_threshold.2 {
+            creator_profile.tier = creator_profile.tier.checked_add(1).unwrap();
+    
+            if creator_profile.tier != previous_tier {
+                emit!(CreatorTierChangedEvent {
+                    creator: creator_profile.creator,
+                    previous_tier,
+                    new_tier: creator_profile.tier,
+                    markets_count: creator_profile.markets_created,
+                    total_volume: creator_profile.total_volume,
+                    traction_score: creator_profile.traction_score,
+                });
+            }
+        }
+    }
+    
+    emit!(PredictionStakedEvent {
+        user: prediction.user,
+        market: market.key(),
+        outcome_index,
+        amount,
+        timestamp: current_time,
+    });
+    
+    msg!("Staked {} on outcome {}", amount, outcome_index);
+    Ok(())
+}
+
+// Pays a winning claim out of the vault: the reward to the claimant, the protocol fee
+// to the treasury, and the creator fee accrued on the market. Returns the reward.
+fn pay_winning_claim<'info>(
+    market: &mut Account<'info, Market>,
+    collateral: &CollateralVault<'_, 'info>,
+    destination: AccountInfo<'info>,
+    protocol_treasury: &InterfaceAccount<'info, token_interface::TokenAccount>,
+    outcome_index: u8,
+    user_stake: u64,
+) -> Result<u64> {
+    let (reward_amount, creator_fee_amount, protocol_fee_amount) =
+        market.settle_claim(outcome_index, user_stake)?;
+    
+    collateral.pay(market, destination, reward_amount)?;
+    
+    if protocol_fee_amount > 0 {
+        collateral.pay_treasury(market, protocol_treasury, protocol_fee_amount)?;
+
+        emit!(ProtocolFeeCollectedEvent {
+            market: market.key(),
+            treasury: protocol_treasury.key(),
+            amount: protocol_fee_amount,
+        });
+    }
+    
+    msg!(
+        "Reward claimed: user={}, creator_fee={}, protocol_fee={}",
+        reward_amount,
+        creator_fee_amount,
+        protocol_fee_amount
+    );
+    
+    Ok(reward_amount)
+}
+
+// Updates the claimant's and creator's profiles after a paid claim
+fn record_claim(
+    user_profile: Option<&mut Account<UserProfile>>,
+    creator_profile: Option<&mut Account<CreatorProfile>>,
+    reward_amount: u64,
+) -> Result<()> {
+    if let Some(user_profile) = user_profile {
+        user_profile.total_winnings = user_profile.total_winnings.checked_add(reward_amount).unwrap();
+        user_profile.winning_predictions = user_profile.winning_predictions.checked_add(1).unwrap();
+        user_profile.last_active_ts = Clock::get()?.unix_timestamp;
+    }
+    
+    if let Some(creator_profile) = creator_profile {
+        creator_profile.traction_score = creator_profile.traction_score.checked_add(reward_amount / 500 + 5).unwrap();
+        
+        let previous_tier = creator_profile.tier;
+        if let Some(creator_tier_threshold) = get_next_tier_threshold(creator_profile) {
+            if creator_profile.total_volume >= creator_tier_threshold.0 && 
+               creator_profile.markets_created >= creator_tier_threshold.1 && 
+               creator_profile.traction_score >= creator_tier_threshold.2 {
+                creator_profile.tier = creator_profile.tier.checked_add(1).unwrap();
+                
+                if creator_profile.tier != previous_tier {
+                    emit!(CreatorTierChangedEvent {
+                        creator: creator_profile.creator,
+                        previous_tier,
+                        new_tier: creator_profile.tier,
+                        markets_count: creator_profile.markets_created,
+                        total_volume: creator_profile.total_volume,
+                        traction_score: creator_profile.traction_score,
+                    });
+                }
+            }
+        }
+    }
+    
+    Ok(())
+}
+
+// Transfers out of the market vault, signed by the market PDA
+fn pay_from_vault<'info>(
+    market: &Account<'info, Market>,
+    market_vault: &Account<'info, TokenAccount>,
+    destination: AccountInfo<'info>,
+    token_program: &Program<'info, Token>,
+    amount: u64,
+) -> Result<()> {
+    let index_bytes = market.market_index.to_le_bytes();
+    let seeds = market.signer_seeds(&index_bytes);
+    let signer = &[&seeds[..]];
+    
+    let cpi_accounts = Transfer {
+        from: market_vault.to_account_info(),
+        to: destination,
+        authority: market.to_account_info(),
+    };
+    
+    let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+    
+    token::transfer(cpi_ctx, amount)
+}
+
+// Where a parimutuel market keeps its collateral: lamports in the SOL vault PDA for
+// native markets, tokens in the SPL market vault otherwise
+struct CollateralVault<'a, 'info> {
+    market_vault: &'a InterfaceAccount<'info, token_interface::TokenAccount>,
+    mint: &'a InterfaceAccount<'info, token_interface::Mint>,
+    sol_vault: Option<&'a SystemAccount<'info>>,
+    token_program: &'a Interface<'info, TokenInterface>,
+    system_program: Option<&'a Program<'info, System>>,
+}
+
+impl<'a, 'info> CollateralVault<'a, 'info> {
+    fn native(&self) -> Result<(&'a SystemAccount<'info>, &'a Program<'info, System>)> {
+        match (self.sol_vault, self.system_program) {
+            (Some(sol_vault), Some(system_program)) => Ok((sol_vault, system_program)),
+            _ => err!(ErrorCode::SolVaultRequired),
+        }
+    }
+    
+    // Native markets move lamports between wallets; SPL markets need the user's token account
+    fn account_for(
+        &self,
+        market: &Market,
+        wallet: &AccountInfo<'info>,
+        token_account: Option<&InterfaceAccount<'info, token_interface::TokenAccount>>,
+    ) -> Result<AccountInfo<'info>> {
+        if market.native_sol {
+            return Ok(wallet.clone());
+        }
+        
+        token_account
+            .map(|token_account| token_account.to_account_info())
+            .ok_or_else(|| error!(ErrorCode::CollateralAccountRequired))
+    }
+    
+    // Collateral held for the market, excluding the SOL vault's rent-exempt reserve
+    fn balance(&self, market: &Market) -> Result<u64> {
+        if !market.native_sol {
+            return Ok(self.market_vault.amount);
+        }
+        
+        let (sol_vault, _) = self.native()?;
+        Ok(sol_vault.lamports().saturating_sub(Rent::get()?.minimum_balance(0)))
+    }
+    
+    // Returns the amount that reached the vault, which a transfer-fee mint makes less than `amount`
+    fn deposit(
+        &self,
+        market: &Market,
+        from: AccountInfo<'info>,
+        authority: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<u64> {
+        if market.native_sol {
+            let (sol_vault, system_program) = self.native()?;
+            let cpi_accounts = system_program::Transfer {
+                from,
+                to: sol_vault.to_account_info(),
+            };
+            
+            let cpi_ctx = CpiContext::new(system_program.to_account_info(), cpi_accounts);
+            
+            system_program::transfer(cpi_ctx, amount)?;
+            return Ok(amount);
+        }
+        
+        let cpi_accounts = TransferChecked {
+            from,
+            mint: self.mint.to_account_info(),
+            to: self.market_vault.to_account_info(),
+            authority,
+        };
+        
+        let cpi_ctx = CpiContext::new(self.token_program.to_account_info(), cpi_accounts);
+        
+        token_interface::transfer_checked(cpi_ctx, amount, self.mint.decimals)?;
+        
+        let vault_info = self.market_vault.to_account_info();
+        let vault_data = vault_info.try_borrow_data()?;
+        let vault_after = token_interface::TokenAccount::try_deserialize(&mut &vault_data[..])?;
+        
+        Ok(vault_after.amount.checked_sub(self.market_vault.amount).unwrap())
+    }
+    
+    fn pay(
+        &self,
+        market: &Account<'info, Market>,
+        destination: AccountInfo<'info>,
+        amount: u64,
+    ) -> Result<()> {
+        if !market.native_sol {
+            let index_bytes = market.market_index.to_le_bytes();
+            let seeds = market.signer_seeds(&index_bytes);
+            let signer = &[&seeds[..]];
+            
+            let cpi_accounts = TransferChecked {
+                from: self.market_vault.to_account_info(),
+                mint: self.mint.to_account_info(),
+                to: destination,
+                authority: market.to_account_info(),
+            };
+            
+            let cpi_ctx = CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer);
+            
+            return token_interface::transfer_checked(cpi_ctx, amount, self.mint.decimals);
+        }
+        
+        let (sol_vault, system_program) = self.native()?;
+        let market_key = market.key();
+        let bump = [market.sol_vault_bump];
+        let seeds = [b"sol_vault".as_ref(), market_key.as_ref(), &bump];
+        let signer = &[&seeds[..]];
+        
+        let cpi_accounts = system_program::Transfer {
+            from: sol_vault.to_account_info(),
+            to: destination,
+        };
+        
+        let cpi_ctx = CpiContext::new_with_signer(system_program.to_account_info(), cpi_accounts, signer);
+        
+        system_program::transfer(cpi_ctx, amount)
+    }
+    
+    // Native markets pay the protocol in lamports, wrapped into the native mint's treasury
+    fn pay_treasury(
+        &self,
+        market: &Account<'info, Market>,
+        protocol_treasury: &InterfaceAccount<'info, token_interface::TokenAccount>,
+        amount: u64,
+    ) -> Result<()> {
+        self.pay(market, protocol_treasury.to_account_info(), amount)?;
+        
+        if market.native_sol {
+            let cpi_accounts = token_interface::SyncNative {
+                account: protocol_treasury.to_account_info(),
+            };
+            
+            token_interface::sync_native(CpiContext::new(self.token_program.to_account_info(), cpi_accounts))?;
+        }
+        
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct CreateCreatorProfile<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + CreatorProfile::SPACE,
+        seeds = [b"creator_profile", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_profile: Account<'info, CreatorProfile>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAIResolver<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AIResolver::SPACE,
+        seeds = [b"ai_resolver", admin.key().as_ref()],
+        bump
+    )]
+    pub ai_resolver: Account<'info, AIResolver>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveMarketViaAI<'info> {
+    #[account(mut)]
+    pub resolver_authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"ai_resolver", resolver_authority.key().as_ref()],
+        bump
+    )]
+    pub ai_resolver: Account<'info, AIResolver>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeMarket<'info> {
+    #[account(mut)]     
+    pub creator: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"creator_profile", creator.key().as_ref()],
+        bump
+    )]
+    pub creator_profile: Account<'info, CreatorProfile>,
+    
+    #[account(
+        init,
+        payer = creator,
+        space = 8 + Market::SPACE,
+        seeds = [b"market", creator.key().as_ref(), &creator_profile.markets_created.to_le_bytes()],
+        bump
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = creator,
+        token::mint = mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(mint::token_program = token_program)]
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        seeds = [b"collateral_mint", mint.key().as_ref()],
+        bump = collateral_config.bump,
+        constraint = collateral_config.enabled @ ErrorCode::CollateralMintDisabled
+    )]
+    pub collateral_config: Account<'info, CollateralMint>,
+    
+    // Only needed to fund the subsidy of an LMSR market
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == creator.key(),
+        constraint = creator_token_account.mint == mint.key()
+    )]
+    pub creator_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    
+    // Only needed for native SOL markets
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct StakePrediction<'info> {
+    // Pays rent for the prediction, so a relayer can cover it for the beneficiary
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    // Owner or approved delegate of the collateral token account, or the wallet
+    // whose lamports fund a native SOL stake
+    #[account(mut)]
+    pub token_authority: Signer<'info>,
+    
+    // Owns the prediction, the outcome tokens and therefore the winnings. It signs so
+    // nobody else can add to its one prediction per market or bump its profile
+    pub beneficiary: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"creator_profile", market.creator.as_ref()],
+        bump
+    )]
+    pub creator_profile: Account<'info, CreatorProfile>,
+    
+    // Created on the first stake and topped up by later ones
+    #[account(
+        init_if_needed,
+        payer = payer,
+        space = 8 + Prediction::SPACE,
+        seeds = [b"prediction", market.key().as_ref(), beneficiary.key().as_ref()],
+        bump
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    // Not needed for native SOL markets, which take lamports from the token authority directly.
+    // The token program checks the authority is the owner or a delegate with enough allowance
+    #[account(
+        mut,
+        constraint = user_token_account.mint == market_vault.mint
+    )]
+    pub user_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump = market.sol_vault_bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    #[account(mut, mint::token_program = token_program)]
+    pub outcome_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = user_outcome_token_account.owner == beneficiary.key(),
+        constraint = user_outcome_token_account.mint == outcome_mint.key()
+    )]
+    pub user_outcome_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", beneficiary.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct BatchStake<'info> {
+    // Pays rent for every prediction in the batch
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    // Signs for the collateral of every entry, as in StakePrediction
+    #[account(mut)]
+    pub token_authority: Signer<'info>,
+    
+    // Signs for the same reason as in StakePrediction
+    pub beneficiary: Signer<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", beneficiary.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+    
+    // Shared by every entry, so a batch cannot mix SPL and Token-2022 collateral
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakePredictionNft<'info> {
+    // Payer, token authority and beneficiary split as in StakePrediction
+    #[account(mut)]
+    pub payer: Signer<'info>,
+    
+    #[account(mut)]
+    pub token_authority: Signer<'info>,
+    
+    // Owns the prediction and receives the position NFT; signs as in StakePrediction
+    pub beneficiary: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"creator_profile", market.creator.as_ref()],
+        bump
+    )]
+    pub creator_profile: Account<'info, CreatorProfile>,
+    
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Prediction::SPACE,
+        seeds = [b"prediction", market.key().as_ref(), beneficiary.key().as_ref()],
+        bump
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    #[account(
+        init,
+        payer = payer,
+        mint::decimals = 0,
+        mint::authority = market,
+        mint::token_program = token_program,
+        seeds = [b"position_nft", prediction.key().as_ref()],
+        bump
+    )]
+    pub position_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        init,
+        payer = payer,
+        associated_token::mint = position_mint,
+        associated_token::authority = beneficiary,
+        associated_token::token_program = token_program
+    )]
+    pub user_position_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    // The token program checks the authority is the owner or a delegate with enough allowance
+    #[account(
+        mut,
+        constraint = user_token_account.mint == market_vault.mint
+    )]
+    pub user_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", beneficiary.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct TransferPrediction<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub new_owner: SystemAccount<'info>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"prediction", market.key().as_ref(), user.key().as_ref()],
+        bump = prediction.bump,
+        constraint = prediction.user == user.key()
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    #[account(
+        init,
+        payer = user,
+        space = 8 + Prediction::SPACE,
+        seeds = [b"prediction", market.key().as_ref(), new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_prediction: Account<'info, Prediction>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_owner_profile: Option<Account<'info, UserProfile>>,
+    
+    // The prediction's outcome mint, or its position NFT mint if it has one
+    #[account(mint::token_program = token_program)]
+    pub claim_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = user_claim_token_account.owner == user.key(),
+        constraint = user_claim_token_account.mint == claim_mint.key()
+    )]
+    pub user_claim_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        constraint = new_owner_claim_token_account.owner == new_owner.key(),
+        constraint = new_owner_claim_token_account.mint == claim_mint.key()
+    )]
+    pub new_owner_claim_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct VoteMarketOutcome<'info> {
+    #[account(mut)]
+    pub voter: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = voter,
+        space = 8 + OutcomeVote::SPACE,
+        seeds = [b"outcome_vote", market.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub outcome_vote: Account<'info, OutcomeVote>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveMarket<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimReward<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = market.resolved == true
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, mint::token_program = token_program)]
+    pub outcome_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = user_outcome_token_account.owner == user.key(),
+        constraint = user_outcome_token_account.mint == outcome_mint.key()
+    )]
+    pub user_outcome_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    // The claimer's own prediction is closed back to them; NFT-backed ones close on claim_position_nft
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"prediction", market.key().as_ref(), user.key().as_ref()],
+        bump = prediction.bump,
+        constraint = prediction.user == user.key(),
+        constraint = prediction.position_mint == Pubkey::default() @ ErrorCode::PositionNftOutstanding
+    )]
+    pub prediction: Option<Account<'info, Prediction>>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    // Not needed for native SOL markets, which pay lamports to the user directly
+    #[account(
+        mut,
+        constraint = user_token_account.owner == user.key(),
+        constraint = user_token_account.mint == market_vault.mint
+    )]
+    pub user_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump = market.sol_vault_bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    #[account(
+        mut,
+        seeds = [b"treasury", market_vault.mint.as_ref()],
+        bump,
+        constraint = protocol_treasury.owner == treasury_authority.key() @ ErrorCode::InvalidTreasury
+    )]
+    pub protocol_treasury: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    /// CHECK: PDA that owns every protocol treasury token account
+    #[account(seeds = [b"treasury_authority"], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+    
+    #[account(
+        mut,
+        seeds = [b"creator_profile", market.creator.as_ref()],
+        bump
+    )]
+    pub creator_profile: Option<Account<'info, CreatorProfile>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimPositionNft<'info> {
+    #[account(mut)]
+    pub holder: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = holder,
+        seeds = [b"prediction", market.key().as_ref(), prediction.user.as_ref()],
+        bump = prediction.bump,
+        constraint = prediction.position_mint == position_mint.key() @ ErrorCode::InvalidPositionMint
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    #[account(mut)]
+    pub position_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = holder_position_token_account.owner == holder.key(),
+        constraint = holder_position_token_account.mint == position_mint.key(),
+        constraint = holder_position_token_account.amount == 1 @ ErrorCode::InvalidPositionMint
+    )]
+    pub holder_position_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = holder_token_account.owner == holder.key(),
+        constraint = holder_token_account.mint == market_vault.mint
+    )]
+    pub holder_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"treasury", market_vault.mint.as_ref()],
+        bump,
+        constraint = protocol_treasury.owner == treasury_authority.key() @ ErrorCode::InvalidTreasury
+    )]
+    pub protocol_treasury: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    /// CHECK: PDA that owns every protocol treasury token account
+    #[account(seeds = [b"treasury_authority"], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", holder.key().as_ref()],
+        bump
+    )]
+    pub holder_profile: Option<Account<'info, UserProfile>>,
+    
+    #[account(
+        mut,
+        seeds = [b"creator_profile", market.creator.as_ref()],
+        bump
+    )]
+    pub creator_profile: Option<Account<'info, CreatorProfile>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct CreatorWithdraw<'info> {
+    #[account(mut)]
+    pub creator: Signer<'info>,
+    
+    #[account(
+        mut,
+        constraint = market.creator == creator.key() @ ErrorCode::Unauthorized
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    // Not needed for native SOL markets
+    #[account(
+        mut,
+        constraint = creator_token_account.owner == creator.key(),
+        constraint = creator_token_account.mint == market_vault.mint
+    )]
+    pub creator_token_account: Option<InterfaceAccount<'info, token_interface::TokenAccount>>,
+    
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump = market.sol_vault_bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SweepMarketVault<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"treasury", market_vault.mint.as_ref()],
+        bump,
+        constraint = protocol_treasury.owner == treasury_authority.key() @ ErrorCode::InvalidTreasury
+    )]
+    pub protocol_treasury: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    /// CHECK: PDA that owns every protocol treasury token account
+    #[account(seeds = [b"treasury_authority"], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump = market.sol_vault_bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseLosingPrediction<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        close = user,
+        seeds = [b"prediction", market.key().as_ref(), user.key().as_ref()],
+        bump = prediction.bump,
+        constraint = prediction.user == user.key()
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    #[account(
+        mut,
+        seeds = [b"user_profile", user.key().as_ref()],
+        bump
+    )]
+    pub user_profile: Option<Account<'info, UserProfile>>,
+}
+
+#[derive(Accounts)]
+pub struct CloseMarket<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(
+        mut,
+        close = creator,
+        constraint = market.resolved == true
+    )]
+    pub market: Account<'info, Market>,
+    
+    #[account(mut, address = market.creator)]
+    pub creator: SystemAccount<'info>,
+    
+    #[account(
+        mut,
+        seeds = [b"market_vault", market.key().as_ref()],
+        bump,
+        token::token_program = token_program,
+        constraint = market_vault.amount == 0 @ ErrorCode::MarketVaultNotEmpty
+    )]
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        seeds = [b"sol_vault", market.key().as_ref()],
+        bump = market.sol_vault_bump
+    )]
+    pub sol_vault: Option<SystemAccount<'info>>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterVoteAuthority<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    pub authority: SystemAccount<'info>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + VoteAuthority::SPACE,
+        seeds = [b"vote_authority", market.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub vote_authority: Account<'info, VoteAuthority>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeVoteResult<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + VoteResult::SPACE,
+        seeds = [b"vote_result", market.key().as_ref()],
+        bump
+    )]
+    pub vote_result: Account<'info, VoteResult>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct StakeWeightedVote<'info> {
+    #[account(mut)]
+    pub voter: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+    
+    #[account(
+        mut,
+        seeds = [b"prediction", market.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub prediction: Account<'info, Prediction>,
+    
+    // The prediction's outcome mint, or its position NFT mint if it has one
+    #[account(mint::token_program = token_program)]
+    pub claim_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = voter_claim_token_account.owner == voter.key(),
+        constraint = voter_claim_token_account.mint == claim_mint.key()
+    )]
+    pub voter_claim_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        init,
+        payer = voter,
+        token::mint = claim_mint,
+        token::authority = market,
+        token::token_program = token_program,
+        seeds = [b"vote_escrow", market.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub vote_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        seeds = [b"vote_result", market.key().as_ref()],
+        bump
+    )]
+    pub vote_result: Account<'info, VoteResult>,
+    
+    #[account(
+        init,
+        payer = voter,
+        space = 8 + OutcomeVote::SPACE,
+        seeds = [b"outcome_vote", market.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub outcome_vote: Account<'info, OutcomeVote>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ReclaimVoteTokens<'info> {
+    #[account(mut)]
+    pub voter: Signer<'info>,
+    
+    pub market: Account<'info, Market>,
+    
+    #[account(mint::token_program = token_program)]
+    pub claim_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    #[account(
+        mut,
+        constraint = voter_claim_token_account.owner == voter.key(),
+        constraint = voter_claim_token_account.mint == claim_mint.key()
+    )]
+    pub voter_claim_token_account: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(
+        mut,
+        token::mint = claim_mint,
+        seeds = [b"vote_escrow", market.key().as_ref(), voter.key().as_ref()],
+        bump
+    )]
+    pub vote_escrow: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
+}
 
 #[derive(Accounts)]
-pub struct CreateCreatorProfile<'info> {
+pub struct ProposeResolution<'info> {
     #[account(mut)]
-    pub creator: Signer<'info>,
+    pub authority: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
     
     #[account(
-        init,
-        payer = creator,
-        space = 8 + CreatorProfile::SPACE,
-        seeds = [b"creator_profile", creator.key().as_ref()],
+        mut,
+        seeds = [b"vote_result", market.key().as_ref()],
         bump
     )]
-    pub creator_profile: Account<'info, CreatorProfile>,
+    pub vote_result: Account<'info, VoteResult>,
+    
+    #[account(
+        mut,
+        seeds = [b"vote_authority", market.key().as_ref(), authority.key().as_ref()],
+        bump
+    )]
+    pub vote_authority: Account<'info, VoteAuthority>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeAIResolver<'info> {
+pub struct FinalizeResolution<'info> {
     #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>,
+    pub admin: Signer<'info>, 
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
     
     #[account(
-        init,
-        payer = admin,
-        space = 8 + AIResolver::SPACE,
-        seeds = [b"ai_resolver", admin.key().as_ref()],
+        mut,
+        seeds = [b"vote_result", market.key().as_ref()],
         bump
     )]
-    pub ai_resolver: Account<'info, AIResolver>,
+    pub vote_result: Account<'info, VoteResult>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveMarketViaAI<'info> {
+pub struct ChallengeResolution<'info> {
     #[account(mut)]
-    pub resolver_authority: Signer<'info>,
+    pub challenger: Signer<'info>,
     
     #[account(mut)]
     pub market: Account<'info, Market>,
     
     #[account(
         mut,
-        seeds = [b"ai_resolver", resolver_authority.key().as_ref()],
+        seeds = [b"vote_result", market.key().as_ref()],
         bump
     )]
-    pub ai_resolver: Account<'info, AIResolver>,
+    pub vote_result: Account<'info, VoteResult>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeMarket<'info> {
-    #[account(mut)]     
-    pub creator: Signer<'info>,
+#[instruction(outcome_index: u8)]
+pub struct InitializeOutcomeMint<'info> {
+    #[account(mut)]
+    pub payer: Signer<'info>,
     
-    #[account(
-        mut,
-        seeds = [b"creator_profile", creator.key().as_ref()],
-        bump
-    )]
-    pub creator_profile: Account<'info, CreatorProfile>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
     
     #[account(
         init,
-        payer = creator,
-        space = 8 + Market::SPACE,
-        seeds = [b"market", creator.key().as_ref(), &creator_profile.markets_created.to_le_bytes()],
+        payer = payer,
+        mint::decimals = collateral_mint.decimals,
+        mint::authority = market,
+        mint::token_program = token_program,
+        seeds = [b"outcome_mint", market.key().as_ref(), &[outcome_index]],
         bump
     )]
-    pub market: Account<'info, Market>,
+    pub outcome_mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
-        init,
-        payer = creator,
-        token::mint = mint,
-        token::authority = market,
         seeds = [b"market_vault", market.key().as_ref()],
-        bump
+        bump,
+        token::token_program = token_program
     )]
-    pub market_vault: Account<'info, TokenAccount>,
+    pub market_vault: InterfaceAccount<'info, token_interface::TokenAccount>,
     
-    pub mint: Account<'info, token::Mint>,
-    pub token_program: Program<'info, Token>,
+    #[account(address = market_vault.mint)]
+    pub collateral_mint: InterfaceAccount<'info, token_interface::Mint>,
+    
+    pub token_program: Interface<'info, TokenInterface>,
     pub system_program: Program<'info, System>,
     pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct StakePrediction<'info> {
+pub struct OpenPosition<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub owner: Signer<'info>,
     
-    #[account(mut)]
     pub market: Account<'info, Market>,
     
-    #[account(
-        mut,
-        seeds = [b"creator_profile", market.creator.as_ref()],
-        bump
-    )]
-    pub creator_profile: Account<'info, CreatorProfile>,
-    
     #[account(
         init,
-        payer = user,
-        space = 8 + Prediction::SPACE,
-        seeds = [b"prediction", market.key().as_ref(), user.key().as_ref()],
+        payer = owner,
+        space = 8 + OutcomePosition::SPACE,
+        seeds = [b"position", market.key().as_ref(), owner.key().as_ref()],
         bump
     )]
-    pub prediction: Account<'info, Prediction>,
+    pub position: Account<'info, OutcomePosition>,
+    
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TradeShares<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+    
+    #[account(mut)]
+    pub market: Account<'info, Market>,
     
     #[account(
         mut,
-        constraint = user_token_account.owner == user.key(),
-        constraint = user_token_account.mint == market_vault.mint
+        seeds = [b"position", market.key().as_ref(), user.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized
     )]
-    pub user_token_account: Account<'info, TokenAccount>,
+    pub position: Account<'info, OutcomePosition>,
     
     #[account(
         mut,
@@ -999,64 +3865,56 @@ pub struct StakePrediction<'info> {
     
     #[account(
         mut,
-        seeds = [b"user_profile", user.key().as_ref()],
-        bump
+        constraint = user_token_account.owner == user.key(),
+        constraint = user_token_account.mint == market_vault.mint
     )]
-    pub user_profile: Option<Account<'info, UserProfile>>,
+    pub user_token_account: Account<'info, TokenAccount>,
     
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct VoteMarketOutcome<'info> {
+#[instruction(outcome_index: u8)]
+pub struct InitializeOrderBook<'info> {
     #[account(mut)]
-    pub voter: Signer<'info>,
+    pub payer: Signer<'info>,
     
-    #[account(mut)]
     pub market: Account<'info, Market>,
     
     #[account(
         init,
-        payer = voter,
-        space = 8 + OutcomeVote::SPACE,
-        seeds = [b"outcome_vote", market.key().as_ref(), voter.key().as_ref()],
+        payer = payer,
+        space = 8 + OrderBook::SPACE,
+        seeds = [b"order_book", market.key().as_ref(), &[outcome_index]],
         bump
     )]
-    pub outcome_vote: Account<'info, OutcomeVote>,
+    pub order_book: Account<'info, OrderBook>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ResolveMarket<'info> {
-    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>,
-    
+pub struct TradeOrderBook<'info> {
     #[account(mut)]
-    pub market: Account<'info, Market>,
+    pub user: Signer<'info>,
     
-    pub system_program: Program<'info, System>,
-}
-
-#[derive(Accounts)]
-pub struct ClaimReward<'info> {
     #[account(mut)]
-    pub user: Signer<'info>,
+    pub market: Account<'info, Market>,
     
     #[account(
         mut,
-        constraint = market.resolved == true
+        seeds = [b"order_book", market.key().as_ref(), &[order_book.outcome_index]],
+        bump = order_book.bump
     )]
-    pub market: Account<'info, Market>,
+    pub order_book: Account<'info, OrderBook>,
     
     #[account(
         mut,
-        seeds = [b"prediction", market.key().as_ref(), user.key().as_ref()],
-        bump = prediction.bump,
-        constraint = prediction.user == user.key()
+        seeds = [b"position", market.key().as_ref(), user.key().as_ref()],
+        bump = position.bump,
+        constraint = position.owner == user.key() @ ErrorCode::Unauthorized
     )]
-    pub prediction: Account<'info, Prediction>,
+    pub position: Account<'info, OutcomePosition>,
     
     #[account(
         mut,
@@ -1072,321 +3930,965 @@ pub struct ClaimReward<'info> {
     )]
     pub user_token_account: Account<'info, TokenAccount>,
     
-    #[account(
-        mut,
-        constraint = creator_token_account.owner == market.creator,
-        constraint = creator_token_account.mint == market_vault.mint
-    )]
-    pub creator_token_account: Account<'info, TokenAccount>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct ConsumeEvents<'info> {
+    pub market: Account<'info, Market>,
     
     #[account(
         mut,
-        constraint = protocol_fee_account.mint == market_vault.mint
+        seeds = [b"order_book", market.key().as_ref(), &[order_book.outcome_index]],
+        bump = order_book.bump
     )]
-    pub protocol_fee_account: Account<'info, TokenAccount>,
+    pub order_book: Account<'info, OrderBook>,
+}
+
+#[derive(Accounts)]
+pub struct QuotePrices<'info> {
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeTreasury<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
     
-    #[account(
-        mut,
-        seeds = [b"user_profile", user.key().as_ref()],
-        bump
-    )]
-    pub user_profile: Option<Account<'info, UserProfile>>,
+    /// CHECK: PDA that owns every protocol treasury token account
+    #[account(seeds = [b"treasury_authority"], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
     
     #[account(
-        mut,
-        seeds = [b"creator_profile", market.creator.as_ref()],
+        init,
+        payer = admin,
+        token::mint = mint,
+        token::authority = treasury_authority,
+        token::token_program = token_program,
+        seeds = [b"treasury", mint.key().as_ref()],
         bump
     )]
-    pub creator_profile: Option<Account<'info, CreatorProfile>>,
+    pub treasury: InterfaceAccount<'info, token_interface::TokenAccount>,
     
-    pub token_program: Program<'info, Token>,
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+    pub rent: Sysvar<'info, Rent>,
 }
 
 #[derive(Accounts)]
-pub struct CloseMarket<'info> {
+pub struct WithdrawTreasury<'info> {
     #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
     pub admin: Signer<'info>,
     
+    /// CHECK: PDA that owns every protocol treasury token account
+    #[account(seeds = [b"treasury_authority"], bump)]
+    pub treasury_authority: UncheckedAccount<'info>,
+    
     #[account(
         mut,
-        close = admin,
-        constraint = market.resolved == true
+        seeds = [b"treasury", treasury.mint.as_ref()],
+        bump,
+        token::token_program = token_program,
+        constraint = treasury.owner == treasury_authority.key() @ ErrorCode::InvalidTreasury
     )]
-    pub market: Account<'info, Market>,
+    pub treasury: InterfaceAccount<'info, token_interface::TokenAccount>,
+    
+    #[account(address = treasury.mint)]
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         mut,
-        seeds = [b"market_vault", market.key().as_ref()],
-        bump,
-        constraint = market_vault.amount == 0 
+        constraint = destination.mint == treasury.mint
     )]
-    pub market_vault: Account<'info, TokenAccount>,
+    pub destination: InterfaceAccount<'info, token_interface::TokenAccount>,
     
-    pub token_program: Program<'info, Token>,
+    pub token_program: Interface<'info, TokenInterface>,
 }
 
 #[derive(Accounts)]
-pub struct RegisterVoteAuthority<'info> {
+pub struct RegisterCollateralMint<'info> {
     #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
     pub admin: Signer<'info>,
     
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    pub authority: SystemAccount<'info>,
+    pub mint: InterfaceAccount<'info, token_interface::Mint>,
     
     #[account(
         init,
         payer = admin,
-        space = 8 + VoteAuthority::SPACE,
-        seeds = [b"vote_authority", market.key().as_ref(), authority.key().as_ref()],
+        space = 8 + CollateralMint::SPACE,
+        seeds = [b"collateral_mint", mint.key().as_ref()],
         bump
     )]
-    pub vote_authority: Account<'info, VoteAuthority>,
+    pub collateral_config: Account<'info, CollateralMint>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct InitializeVoteResult<'info> {
-    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+pub struct UpdateCollateralMint<'info> {
+    #[account(constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
     pub admin: Signer<'info>,
     
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
     #[account(
-        init,
-        payer = admin,
-        space = 8 + VoteResult::SPACE,
-        seeds = [b"vote_result", market.key().as_ref()],
-        bump
+        mut,
+        seeds = [b"collateral_mint", collateral_config.mint.as_ref()],
+        bump = collateral_config.bump
     )]
-    pub vote_result: Account<'info, VoteResult>,
-    
-    pub system_program: Program<'info, System>,
+    pub collateral_config: Account<'info, CollateralMint>,
 }
 
 #[derive(Accounts)]
-pub struct StakeWeightedVote<'info> {
-    #[account(mut)]
-    pub voter: Signer<'info>,
-    
+pub struct InitializeUserProfile<'info> {
     #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    #[account(
-        mut,
-        seeds = [b"prediction", market.key().as_ref(), voter.key().as_ref()],
-        bump
-    )]
-    pub prediction: Account<'info, Prediction>,
-    
-    #[account(
-        mut,
-        seeds = [b"vote_result", market.key().as_ref()],
-        bump
-    )]
-    pub vote_result: Account<'info, VoteResult>,
+    pub user: Signer<'info>,
     
     #[account(
         init,
-        payer = voter,
-        space = 8 + OutcomeVote::SPACE,
-        seeds = [b"outcome_vote", market.key().as_ref(), voter.key().as_ref()],
+        payer = user,
+        space = 8 + UserProfile::SPACE,
+        seeds = [b"user_profile", user.key().as_ref()],
         bump
     )]
-    pub outcome_vote: Account<'info, OutcomeVote>,
+    pub user_profile: Account<'info, UserProfile>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct ProposeResolution<'info> {
-    #[account(mut)]
-    pub authority: Signer<'info>,
+pub struct UpdateCreatorTier<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
     
     #[account(mut)]
-    pub market: Account<'info, Market>,
+    pub creator_profile: Account<'info, CreatorProfile>,
     
-    #[account(
-        mut,
-        seeds = [b"vote_result", market.key().as_ref()],
-        bump
-    )]
-    pub vote_result: Account<'info, VoteResult>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeProtocolStats<'info> {
+    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
+    pub admin: Signer<'info>,
     
     #[account(
-        mut,
-        seeds = [b"vote_authority", market.key().as_ref(), authority.key().as_ref()],
+        init,
+        payer = admin,
+        space = 8 + ProtocolStats::SPACE,
+        seeds = [b"protocol_stats"],
         bump
     )]
-    pub vote_authority: Account<'info, VoteAuthority>,
+    pub protocol_stats: Account<'info, ProtocolStats>,
     
     pub system_program: Program<'info, System>,
 }
 
 #[derive(Accounts)]
-pub struct FinalizeResolution<'info> {
+pub struct UpdateProtocolStats<'info> {
     #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>, 
+    pub admin: Signer<'info>,
     
     #[account(mut)]
-    pub market: Account<'info, Market>,
+    pub protocol_stats: Account<'info, ProtocolStats>,
     
-    #[account(
-        mut,
-        seeds = [b"vote_result", market.key().as_ref()],
-        bump
-    )]
-    pub vote_result: Account<'info, VoteResult>,
+    #[account(mut)]
+    pub market: Option<Account<'info, Market>>,
+    
+    #[account(mut)]
+    pub prediction: Option<Account<'info, Prediction>>,
+    
+    #[account(mut)]
+    pub markets: Option<Account<'info, Market>>,
     
     pub system_program: Program<'info, System>,
 }
 
-#[derive(Accounts)]
-pub struct ChallengeResolution<'info> {
-    #[account(mut)]
-    pub challenger: Signer<'info>,
-    
-    #[account(mut)]
-    pub market: Account<'info, Market>,
-    
-    #[account(
-        mut,
-        seeds = [b"vote_result", market.key().as_ref()],
-        bump
-    )]
-    pub vote_result: Account<'info, VoteResult>,
-    
-    pub system_program: Program<'info, System>,
+#[account]
+#[derive(Default)]
+pub struct Market {
+    pub creator: Pubkey,
+    pub market_index: u32,
+    pub question: String,
+    pub outcomes: Vec<String>,
+    pub ai_score: f32,
+    pub market_type: u8, 
+    pub deadline: i64,
+    pub ai_suggested_deadline: i64,
+    pub resolved: bool,
+    pub resolved_at: i64,
+    pub winning_outcome: Option<u8>,
+    pub scalar_lower: i64,
+    pub scalar_upper: i64,
+    pub resolved_value: Option<i64>,
+    pub payout_numerators: Vec<u64>,
+    pub invalid: bool,
+    pub total_pool: u64,
+    pub creator_fee_bps: u16,
+    pub protocol_fee_bps: u16,
+    pub creator_fees_accrued: u64,
+    pub fee_model: u8,
+    pub total_claimed: u64,
+    pub claimed_winning_stake: u64,
+    pub swept: bool,
+    pub stakes_per_outcome: Vec<u64>, 
+    pub outcome_mints: Vec<Pubkey>,   // SPL mint backing each outcome's stakes
+    pub trading_mode: u8,
+    pub amm_reserves: Vec<u64>,       // Outcome shares held by the AMM pool
+    pub outstanding_shares: Vec<u64>, // Outcome shares held by traders
+    pub lp_total_shares: u64,
+    pub lp_fees_accrued: u64,
+    pub lmsr_liquidity: u64,          // LMSR liquidity parameter b
+    pub clob_escrow: u64,             // Vault collateral owed to order book bids and makers
+    pub native_sol: bool,             // Collateral is lamports in the SOL vault PDA
+    pub sol_vault_bump: u8,
+    pub min_stake: u64,               // Smallest stake per transaction
+    pub max_stake: u64,               // Largest stake per transaction
+    pub max_position: u64,            // Most one wallet may stake in the market
+    pub ai_resolvable: bool, 
+    pub bump: u8,
+}
+
+impl Market {
+    pub const SPACE: usize = 32 + // creator
+                            4 + // market_index
+                            4 + 200 + // question (assume max 200 chars)
+                            4 + 5 * (4 + 50) + // outcomes (5 outcomes with 50 chars each)
+                            4 + // ai_score
+                            1 + // market_type
+                            8 + // deadline
+                            8 + // ai_suggested_deadline
+                            1 + // resolved
+                            8 + // resolved_at
+                            1 + 1 + // winning_outcome (Option<u8>)
+                            8 + // scalar_lower
+                            8 + // scalar_upper
+                            1 + 8 + // resolved_value (Option<i64>)
+                            4 + 5 * 8 + // payout_numerators (5 outcomes max)
+                            1 + // invalid
+                            8 + // total_pool
+                            2 + // creator_fee_bps
+                            2 + // protocol_fee_bps
+                            8 + // creator_fees_accrued
+                            1 + // fee_model
+                            8 + // total_claimed
+                            8 + // claimed_winning_stake
+                            1 + // swept
+                            4 + 5 * 8 + // stakes_per_outcome (5 outcomes max)
+                            4 + 5 * 32 + // outcome_mints (5 outcomes max)
+                            1 + // trading_mode
+                            4 + 5 * 8 + // amm_reserves (5 outcomes max)
+                            4 + 5 * 8 + // outstanding_shares (5 outcomes max)
+                            8 + // lp_total_shares
+                            8 + // lp_fees_accrued
+                            8 + // lmsr_liquidity
+                            8 + // clob_escrow
+                            1 + // native_sol
+                            1 + // sol_vault_bump
+                            8 + // min_stake
+                            8 + // max_stake
+                            8 + // max_position
+                            1 + // ai_resolvable
+                            1 + // bump
+                            50; // padding
+
+    // Canonical signer seeds for the market PDA, which owns the market vault:
+    // [b"market", creator, market_index (le), bump], matching InitializeMarket
+    pub fn signer_seeds<'a>(&'a self, index_bytes: &'a [u8; 4]) -> [&'a [u8]; 4] {
+        [
+            b"market".as_ref(),
+            self.creator.as_ref(),
+            index_bytes.as_ref(),
+            std::slice::from_ref(&self.bump),
+        ]
+    }
+
+    // Checks shared by every way of staking on a parimutuel market
+    pub fn check_stake(&self, outcome_index: u8, amount: u64) -> Result<()> {
+        require!(
+            (outcome_index as usize) < self.outcomes.len(),
+            ErrorCode::InvalidOutcomeIndex
+        );
+        
+        require!(amount >= self.min_stake, ErrorCode::StakeBelowMinimum);
+        require!(amount <= self.max_stake, ErrorCode::StakeAboveMaximum);
+        
+        require!(
+            self.trading_mode == TradingMode::Parimutuel as u8,
+            ErrorCode::NotParimutuelMarket
+        );
+        
+        require!(!self.resolved, ErrorCode::MarketAlreadyResolved);
+        
+        let current_time = Clock::get()?.unix_timestamp;
+        require!(self.deadline > current_time, ErrorCode::MarketExpired);
+        
+        Ok(())
+    }
+
+    // The outcome's share of the pool after a stake of `amount`, which sets the staker's odds
+    pub fn outcome_share_bps(&self, outcome_index: u8, amount: u64) -> u64 {
+        let outcome_stake = self
+            .stakes_per_outcome
+            .get(outcome_index as usize)
+            .copied()
+            .unwrap_or(0) as u128
+            + amount as u128;
+        let total_pool = self.total_pool as u128 + amount as u128;
+        
+        if total_pool == 0 {
+            return 0;
+        }
+        
+        (outcome_stake * 10000 / total_pool) as u64
+    }
+
+    pub fn check_position(&self, position: u64) -> Result<()> {
+        require!(position <= self.max_position, ErrorCode::PositionCapExceeded);
+        Ok(())
+    }
+
+    pub fn record_stake(&mut self, outcome_index: u8, amount: u64) {
+        self.total_pool = self.total_pool.checked_add(amount).unwrap();
+        
+        if self.stakes_per_outcome.len() < self.outcomes.len() {
+            self.stakes_per_outcome = vec![0; self.outcomes.len()];
+        }
+        
+        self.stakes_per_outcome[outcome_index as usize] =
+            self.stakes_per_outcome[outcome_index as usize].checked_add(amount).unwrap();
+    }
+
+    // Resolves to a single winner, i.e. a one-hot payout vector.
+    // No winner (or INVALID_OUTCOME) resolves the market as Invalid.
+    pub fn set_winning_outcome(&mut self, winning_outcome: Option<u8>) -> Result<()> {
+        let outcome = match winning_outcome {
+            Some(outcome) if outcome != INVALID_OUTCOME => outcome,
+            _ => {
+                self.set_invalid();
+                return Ok(());
+            }
+        };
+        
+        require!(
+            (outcome as usize) < self.outcomes.len(),
+            ErrorCode::InvalidOutcomeIndex
+        );
+        
+        self.winning_outcome = Some(outcome);
+        self.payout_numerators = vec![0; self.outcomes.len()];
+        self.payout_numerators[outcome as usize] = 1;
+        Ok(())
+    }
+
+    // Paying each outcome in proportion to its own stake refunds every position.
+    // Share markets instead pay every outcome share the same 1/n.
+    pub fn set_invalid(&mut self) {
+        self.invalid = true;
+        self.winning_outcome = None;
+        self.payout_numerators = if self.is_share_market() {
+            vec![1; self.outcomes.len()]
+        } else {
+            self.stakes_per_outcome.clone()
+        };
+    }
+
+    // Scalar markets resolve to a value and share markets to a single winner, so
+    // arbitrary vectors are only for parimutuel outcome markets
+    pub fn set_payout_numerators(&mut self, payout_numerators: Vec<u64>) -> Result<()> {
+        require!(
+            self.market_type != MarketType::Scalar as u8,
+            ErrorCode::ScalarValueRequired
+        );
+        require!(!self.is_share_market(), ErrorCode::PayoutVectorUnsupported);
+        require!(
+            payout_numerators.len() == self.outcomes.len(),
+            ErrorCode::InvalidPayoutVector
+        );
+        require!(
+            payout_numerators.iter().any(|numerator| *numerator > 0),
+            ErrorCode::InvalidPayoutVector
+        );
+        
+        self.winning_outcome = None;
+        self.payout_numerators = payout_numerators;
+        Ok(())
+    }
+
+    // LONG is paid (value - lower) / (upper - lower) of the range, SHORT the rest
+    pub fn set_scalar_value(&mut self, resolved_value: i64) {
+        let value = resolved_value.clamp(self.scalar_lower, self.scalar_upper);
+        let range = (self.scalar_upper as i128 - self.scalar_lower as i128) as u64;
+        let long = (value as i128 - self.scalar_lower as i128) as u64;
+        
+        self.resolved_value = Some(resolved_value);
+        self.winning_outcome = None;
+        self.payout_numerators = vec![long, range - long];
+    }
+
+    // Fraction (numerator, denominator) of the pool paid to stakers of an outcome.
+    // Outcomes nobody staked on are left out of the denominator so their share
+    // goes to the other paid outcomes instead of being stranded in the vault.
+    pub fn payout_fraction(&self, outcome_index: u8) -> Result<(u128, u128)> {
+        require!(!self.payout_numerators.is_empty(), ErrorCode::NoWinningOutcome);
+        require!(
+            (outcome_index as usize) < self.payout_numerators.len(),
+            ErrorCode::InvalidOutcomeIndex
+        );
+        
+        let denominator = self
+            .payout_numerators
+            .iter()
+            .zip(self.stakes_per_outcome.iter())
+            .filter(|(_, stake)| **stake > 0)
+            .map(|(numerator, _)| *numerator as u128)
+            .sum::<u128>();
+        
+        Ok((self.payout_numerators[outcome_index as usize] as u128, denominator))
+    }
+
+    pub fn is_winning_outcome(&self, outcome_index: u8) -> Result<bool> {
+        Ok(self.payout_fraction(outcome_index)?.0 > 0)
+    }
+
+    // Token-backed records can go once the market resolves, even winning ones whose tokens
+    // were sold or claimed. A winning NFT-backed record is what claim_position_nft pays against
+    pub fn check_prediction_closable(&self, prediction: &Prediction) -> Result<()> {
+        require!(self.resolved, ErrorCode::MarketNotResolved);
+        require!(
+            prediction.position_mint == Pubkey::default()
+                || !self.is_winning_outcome(prediction.outcome_index)?,
+            ErrorCode::NotLosingPrediction
+        );
+        Ok(())
+    }
+
+    // Total stake entitled to a payout; sweeping waits until all of it is claimed
+    pub fn claimable_stake(&self) -> u64 {
+        self.stakes_per_outcome
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| self.is_winning_outcome(*i as u8).unwrap_or(false))
+            .map(|(_, stake)| *stake)
+            .sum()
+    }
+
+    pub fn outcome_index_for_mint(&self, mint: &Pubkey) -> Result<u8> {
+        let index = self
+            .outcome_mints
+            .iter()
+            .position(|outcome_mint| outcome_mint == mint && *mint != Pubkey::default())
+            .ok_or(ErrorCode::InvalidOutcomeMint)?;
+        
+        Ok(index as u8)
+    }
+
+    pub fn is_share_market(&self) -> bool {
+        self.trading_mode != TradingMode::Parimutuel as u8
+    }
+
+    // Collateral owed for a set of outcome shares; a complete set always pays 1
+    pub fn share_payout(&self, shares: &[u64]) -> Result<u64> {
+        require!(!self.payout_numerators.is_empty(), ErrorCode::NoWinningOutcome);
+        
+        let denominator = self.payout_numerators.iter().map(|n| *n as u128).sum::<u128>();
+        let numerator = shares
+            .iter()
+            .zip(self.payout_numerators.iter())
+            .map(|(shares, numerator)| (*shares as u128) * (*numerator as u128))
+            .sum::<u128>();
+        
+        Ok((numerator / denominator) as u64)
+    }
+
+    // Runs a buy against the market maker, returning (shares out, fee)
+    pub fn apply_buy(&mut self, outcome_index: usize, amount: u64) -> Result<(u64, u64)> {
+        if self.trading_mode == TradingMode::Lmsr as u8 {
+            let shares_out = lmsr_buy_shares(&self.outstanding_shares, self.lmsr_liquidity, outcome_index, amount)
+                .ok_or(ErrorCode::InsufficientLiquidity)?;
+            
+            self.outstanding_shares[outcome_index] = self.outstanding_shares[outcome_index].checked_add(shares_out).unwrap();
+            self.total_pool = self.total_pool.checked_add(amount).unwrap();
+            self.require_lmsr_solvent()?;
+            
+            return Ok((shares_out, 0));
+        }
+        
+        require!(self.lp_total_shares > 0, ErrorCode::InsufficientLiquidity);
+        
+        let fee = amount.checked_mul(AMM_FEE_BPS).unwrap() / 10000;
+        let investment = amount.checked_sub(fee).unwrap();
+        
+        let shares_out = cpmm_buy_shares(&self.amm_reserves, outcome_index, investment)
+            .ok_or(ErrorCode::InsufficientLiquidity)?;
+        
+        // The investment mints complete sets into the pool, which then releases
+        // `shares_out` of the bought outcome to the trader
+        for reserve in self.amm_reserves.iter_mut() {
+            *reserve = reserve.checked_add(investment).unwrap();
+        }
+        self.amm_reserves[outcome_index] = self.amm_reserves[outcome_index].checked_sub(shares_out).unwrap();
+        self.outstanding_shares[outcome_index] = self.outstanding_shares[outcome_index].checked_add(shares_out).unwrap();
+        self.total_pool = self.total_pool.checked_add(investment).unwrap();
+        self.lp_fees_accrued = self.lp_fees_accrued.checked_add(fee).unwrap();
+        
+        Ok((shares_out, fee))
+    }
+
+    // Runs a sell against the market maker, returning (shares in, fee)
+    pub fn apply_sell(&mut self, outcome_index: usize, return_amount: u64) -> Result<(u64, u64)> {
+        if self.trading_mode == TradingMode::Lmsr as u8 {
+            let shares_in = lmsr_sell_shares(&self.outstanding_shares, self.lmsr_liquidity, outcome_index, return_amount)
+                .ok_or(ErrorCode::InsufficientLiquidity)?;
+            require!(
+                self.outstanding_shares[outcome_index] >= shares_in,
+                ErrorCode::InsufficientShares
+            );
+            
+            self.outstanding_shares[outcome_index] = self.outstanding_shares[outcome_index].checked_sub(shares_in).unwrap();
+            self.total_pool = self.total_pool.checked_sub(return_amount).unwrap();
+            self.require_lmsr_solvent()?;
+            
+            return Ok((shares_in, 0));
+        }
+        
+        require!(self.lp_total_shares > 0, ErrorCode::InsufficientLiquidity);
+        
+        // Burn enough complete sets to pay `return_amount` plus the swap fee
+        let gross = ((return_amount as u128) * 10000).div_ceil(10000 - AMM_FEE_BPS as u128) as u64;
+        let fee = gross.checked_sub(return_amount).unwrap();
+        
+        let shares_in = cpmm_sell_shares(&self.amm_reserves, outcome_index, gross)
+            .ok_or(ErrorCode::InsufficientLiquidity)?;
+        require!(
+            self.outstanding_shares[outcome_index] >= shares_in,
+            ErrorCode::InsufficientShares
+        );
+        
+        for reserve in self.amm_reserves.iter_mut() {
+            *reserve = reserve.checked_sub(gross).unwrap();
+        }
+        self.amm_reserves[outcome_index] = self.amm_reserves[outcome_index].checked_add(shares_in).unwrap();
+        self.outstanding_shares[outcome_index] = self.outstanding_shares[outcome_index].checked_sub(shares_in).unwrap();
+        self.total_pool = self.total_pool.checked_sub(gross).unwrap();
+        self.lp_fees_accrued = self.lp_fees_accrued.checked_add(fee).unwrap();
+        
+        Ok((shares_in, fee))
+    }
+
+    // One unit of collateral backs one share of every outcome, so the pool and each
+    // outcome's supply move together
+    pub fn mint_complete_sets(&mut self, amount: u64) {
+        for outstanding in self.outstanding_shares.iter_mut() {
+            *outstanding = outstanding.checked_add(amount).unwrap();
+        }
+        self.total_pool = self.total_pool.checked_add(amount).unwrap();
+    }
+
+    pub fn burn_complete_sets(&mut self, amount: u64) -> Result<()> {
+        require!(
+            self.outstanding_shares.iter().all(|outstanding| *outstanding >= amount)
+                && self.total_pool >= amount,
+            ErrorCode::InsufficientShares
+        );
+        
+        for outstanding in self.outstanding_shares.iter_mut() {
+            *outstanding = outstanding.checked_sub(amount).unwrap();
+        }
+        self.total_pool = self.total_pool.checked_sub(amount).unwrap();
+        Ok(())
+    }
+
+    // The collateral held for an LMSR market must cover the largest possible payout,
+    // whatever rounding the fixed-point math did
+    fn require_lmsr_solvent(&self) -> Result<()> {
+        let max_payout = self.outstanding_shares.iter().copied().max().unwrap_or(0);
+        require!(self.total_pool >= max_payout, ErrorCode::InsufficientLiquidity);
+        Ok(())
+    }
+
+    pub fn share_prices(&self) -> Result<Vec<u64>> {
+        let prices = match self.trading_mode {
+            1 => cpmm_prices(&self.amm_reserves),
+            2 => lmsr_prices(&self.outstanding_shares, self.lmsr_liquidity),
+            _ => return Err(ErrorCode::NotAmmMarket.into()),
+        };
+        
+        prices.ok_or(ErrorCode::InsufficientLiquidity.into())
+    }
+
+    // Subsidy left over once an LMSR market resolves, returned to the creator
+    pub fn lmsr_unused_subsidy(&self) -> Result<u64> {
+        if self.trading_mode != TradingMode::Lmsr as u8 || !self.resolved || self.swept {
+            return Ok(0);
+        }
+        
+        let owed = self.share_payout(&self.outstanding_shares)?;
+        Ok(self.total_pool.saturating_sub(self.total_claimed).saturating_sub(owed))
+    }
+
+    // Whether anything is still owed to winners; sweeping waits until it is not
+    pub fn has_unclaimed_payouts(&self) -> bool {
+        if self.is_share_market() {
+            return self.lp_total_shares > 0
+                || self.clob_escrow > 0
+                || self.share_payout(&self.outstanding_shares).unwrap_or(0) > 0;
+        }
+        
+        self.claimed_winning_stake < self.claimable_stake()
+    }
+
+    // Marks the vault swept and returns the dust the treasury may take. An unused LMSR
+    // subsidy is moved into creator_fees_accrued first so creator_withdraw can still pay it
+    pub fn sweep(&mut self, vault_balance: u64) -> Result<u64> {
+        require!(!self.swept, ErrorCode::MarketVaultSwept);
+        
+        let unused_subsidy = self.lmsr_unused_subsidy()?;
+        self.creator_fees_accrued = self.creator_fees_accrued.checked_add(unused_subsidy).unwrap();
+        self.total_pool = self.total_pool.checked_sub(unused_subsidy).unwrap();
+        self.swept = true;
+        
+        // Accrued fees and order book escrow are not dust and stay behind for their owners
+        Ok(vault_balance
+            .saturating_sub(self.creator_fees_accrued)
+            .saturating_sub(self.lp_fees_accrued)
+            .saturating_sub(self.clob_escrow))
+    }
+
+    // Gross share of the pool owed to a winning stake, before fees
+    pub fn winning_share(&self, outcome_index: u8, user_stake: u64) -> Result<u128> {
+        let (numerator, denominator) = self.payout_fraction(outcome_index)?;
+        let outcome_stakes = self.stakes_per_outcome[outcome_index as usize];
+        require!(outcome_stakes > 0, ErrorCode::InvalidDistribution);
+        
+        let outcome_pool = (self.total_pool as u128)
+            .checked_mul(numerator)
+            .unwrap()
+            .checked_div(denominator)
+            .unwrap();
+        let user_share_numerator = (user_stake as u128).checked_mul(outcome_pool).unwrap();
+        Ok(user_share_numerator.checked_div(outcome_stakes as u128).unwrap())
+    }
+
+    // Books a winning claim and returns (reward, creator fee, protocol fee). The creator
+    // fee stays in the vault as creator_fees_accrued; the caller pays out the other two
+    pub fn settle_claim(&mut self, outcome_index: u8, user_stake: u64) -> Result<(u64, u64, u64)> {
+        require!(self.resolved, ErrorCode::MarketNotResolved);
+        require!(!self.swept, ErrorCode::MarketVaultSwept);
+        require!(
+            self.is_winning_outcome(outcome_index)?,
+            ErrorCode::NotWinningPrediction
+        );
+        
+        let user_share = self.winning_share(outcome_index, user_stake)?;
+        
+        // Invalid markets are pure refunds, so no fees are charged
+        let (creator_fee, protocol_fee) = if self.invalid {
+            (0, 0)
+        } else {
+            calculate_fees(
+                user_share,
+                user_stake,
+                self.creator_fee_bps,
+                self.protocol_fee_bps,
+                self.fee_model,
+            )
+        };
+        
+        let reward = user_share
+            .checked_sub(creator_fee)
+            .unwrap()
+            .checked_sub(protocol_fee)
+            .unwrap() as u64;
+        
+        self.creator_fees_accrued = self.creator_fees_accrued.checked_add(creator_fee as u64).unwrap();
+        self.total_claimed = self.total_claimed.checked_add(reward).unwrap();
+        self.claimed_winning_stake = self.claimed_winning_stake.checked_add(user_stake).unwrap();
+        
+        Ok((reward, creator_fee as u64, protocol_fee as u64))
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct Prediction {
+    pub user: Pubkey,
+    pub market: Pubkey,
+    pub outcome_index: u8,
+    pub amount: u64,
+    pub timestamp: i64,
+    pub position_mint: Pubkey, // 1-of-1 position NFT, or default for outcome tokens
+    pub bump: u8,
+}
+
+impl Prediction {
+    pub const SPACE: usize = 32 + // user
+                            32 + // market
+                            1 + // outcome_index
+                            8 + // amount
+                            8 + // timestamp
+                            32 + // position_mint
+                            1 + // bump
+                            30; // padding
+
+    // Records fresh from init are zeroed. An existing one can only grow on its own outcome,
+    // and never when NFT-backed, since the position NFT stands for a fixed stake
+    pub fn check_top_up(&self, outcome_index: u8) -> Result<()> {
+        if self.user != Pubkey::default() {
+            require!(
+                self.position_mint == Pubkey::default() && self.outcome_index == outcome_index,
+                ErrorCode::InvalidTopUp
+            );
+        }
+        Ok(())
+    }
+
+    // Outcome tokens count up to the stake they were minted for; the position NFT counts for all of it
+    pub fn vote_weight(&self, held: u64) -> u64 {
+        if self.position_mint == Pubkey::default() {
+            held.min(self.amount)
+        } else if held > 0 {
+            self.amount
+        } else {
+            0
+        }
+    }
+}
+
+#[account]
+#[derive(Default)]
+pub struct OutcomePosition {
+    pub market: Pubkey,
+    pub owner: Pubkey,
+    pub shares: Vec<u64>,   // Outcome shares held, per outcome
+    pub lp_shares: u64,     // Share of the market's AMM liquidity pool
+    pub free_collateral: u64, // Proceeds of filled asks, withdrawn with settle_funds
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-pub struct InitializeUserProfile<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
-    
-    #[account(
-        init,
-        payer = user,
-        space = 8 + UserProfile::SPACE,
-        seeds = [b"user_profile", user.key().as_ref()],
-        bump
-    )]
-    pub user_profile: Account<'info, UserProfile>,
-    
-    pub system_program: Program<'info, System>,
+impl OutcomePosition {
+    pub const SPACE: usize = 32 + // market
+                            32 + // owner
+                            4 + 5 * 8 + // shares (5 outcomes max)
+                            8 + // lp_shares
+                            8 + // free_collateral
+                            1 + // bump
+                            30; // padding
 }
 
-#[derive(Accounts)]
-pub struct UpdateCreatorTier<'info> {
-    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>,
-    
-    #[account(mut)]
-    pub creator_profile: Account<'info, CreatorProfile>,
-    
-    pub system_program: Program<'info, System>,
+// Admin-approved collateral mint, at ["collateral_mint", mint]
+#[account]
+#[derive(Default)]
+pub struct CollateralMint {
+    pub mint: Pubkey,
+    pub decimals: u8,
+    pub min_stake: u64,     // Smallest stake accepted by markets created in this mint
+    pub enabled: bool,      // Whether new markets can be created in this mint
+    pub bump: u8,
 }
 
-#[derive(Accounts)]
-pub struct InitializeProtocolStats<'info> {
-    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>,
-    
-    #[account(
-        init,
-        payer = admin,
-        space = 8 + ProtocolStats::SPACE,
-        seeds = [b"protocol_stats"],
-        bump
-    )]
-    pub protocol_stats: Account<'info, ProtocolStats>,
-    
-    pub system_program: Program<'info, System>,
+impl CollateralMint {
+    pub const SPACE: usize = 32 + // mint
+                            1 + // decimals
+                            8 + // min_stake
+                            1 + // enabled
+                            1 + // bump
+                            30; // padding
 }
 
-#[derive(Accounts)]
-pub struct UpdateProtocolStats<'info> {
-    #[account(mut, constraint = is_admin(&admin.key()) @ ErrorCode::Unauthorized)]
-    pub admin: Signer<'info>,
-    
-    #[account(mut)]
-    pub protocol_stats: Account<'info, ProtocolStats>,
-    
-    #[account(mut)]
-    pub market: Option<Account<'info, Market>>,
-    
-    #[account(mut)]
-    pub prediction: Option<Account<'info, Prediction>>,
-    
-    #[account(mut)]
-    pub markets: Option<Account<'info, Market>>,
-    
-    pub system_program: Program<'info, System>,
+// One stake of a batch_stake, applied to the matching remaining_accounts group
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchStakeEntry {
+    pub group: u8,                            // Index of this entry's account group in remaining_accounts
+    pub outcome_index: u8,
+    pub amount: u64,
+    pub max_outcome_share_bps: Option<u16>,   // Same slippage limit as stake_prediction
 }
 
-#[account]
-#[derive(Default)]
-pub struct Market {
-    pub creator: Pubkey,
+// Everything a creator chooses at create_market; unset options take their defaults
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct CreateMarketParams {
     pub question: String,
-    pub outcomes: Vec<String>,
+    pub outcomes: Vec<String>,                  // Must be empty for binary and scalar markets
     pub ai_score: f32,
-    pub market_type: u8, 
-    pub deadline: i64,
-    pub ai_suggested_deadline: i64,
-    pub resolved: bool,
-    pub winning_outcome: Option<u8>,
-    pub total_pool: u64,
-    pub creator_fee_bps: u16,
-    pub protocol_fee_bps: u16,
-    pub stakes_per_outcome: Vec<u64>, 
-    pub ai_resolvable: bool, 
-    pub bump: u8,
+    pub ai_recommended_resolution_time: i64,
+    pub ai_classification: u8,                  // MarketType
+    pub creator_metadata: String,
+    pub creator_fee_bps: Option<u16>,           // Ignored; fees follow the creator's tier
+    pub ai_resolvable: Option<bool>,            // Defaults to true
+    pub fee_model: Option<u8>,                  // Defaults to FeeModel::TotalShare
+    pub scalar_lower_bound: Option<i64>,        // Required for scalar markets
+    pub scalar_upper_bound: Option<i64>,        // Required for scalar markets
+    pub trading_mode: Option<u8>,               // Defaults to TradingMode::Parimutuel
+    pub lmsr_liquidity: Option<u64>,            // Required for LMSR markets
+    pub native_sol: Option<bool>,               // Defaults to false
+    pub stake_limits: Option<StakeLimits>,
 }
 
-impl Market {
-    pub const SPACE: usize = 32 + // creator
-                            4 + 200 + // question (assume max 200 chars)
-                            4 + 5 * (4 + 50) + // outcomes (5 outcomes with 50 chars each)
-                            4 + // ai_score
-                            1 + // market_type
-                            8 + // deadline
-                            8 + // ai_suggested_deadline
-                            1 + // resolved
-                            1 + 1 + // winning_outcome (Option<u8>)
-                            8 + // total_pool
-                            2 + // creator_fee_bps
-                            2 + // protocol_fee_bps
-                            4 + 5 * 8 + // stakes_per_outcome (5 outcomes max)
-                            1 + // ai_resolvable
-                            1 + // bump
-                            50; // padding
+// Per-market stake limits chosen at create_market; unset fields take the defaults
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct StakeLimits {
+    pub min_stake: Option<u64>,     // Defaults to the collateral mint's minimum
+    pub max_stake: Option<u64>,     // Defaults to no limit
+    pub max_position: Option<u64>,  // Defaults to no limit
+}
+
+impl StakeLimits {
+    // Returns (min_stake, max_stake, max_position). The minimum can never undercut the
+    // collateral mint's, and every stake must be at least one base unit
+    pub fn resolve(&self, registered_min_stake: u64) -> Result<(u64, u64, u64)> {
+        let min_stake = self.min_stake.unwrap_or(registered_min_stake.max(1));
+        let max_stake = self.max_stake.unwrap_or(u64::MAX);
+        let max_position = self.max_position.unwrap_or(u64::MAX);
+        
+        require!(
+            min_stake > 0 && min_stake >= registered_min_stake,
+            ErrorCode::InvalidStakeLimits
+        );
+        require!(max_stake >= min_stake, ErrorCode::InvalidStakeLimits);
+        require!(max_position >= min_stake, ErrorCode::InvalidStakeLimits);
+        
+        Ok((min_stake, max_stake, max_position))
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct Order {
+    pub order_id: u64,
+    pub position: Pubkey,   // OutcomePosition of the order's owner
+    pub price: u64,         // Collateral per share, in PRICE_SCALE units
+    pub quantity: u64,      // Shares left to fill
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct FillEvent {
+    pub maker_position: Pubkey,
+    pub maker_side: u8,
+    pub price: u64,
+    pub quantity: u64,
+    pub collateral: u64,    // Owed to the maker of a filled ask or an evicted bid
+    pub evicted: bool,      // Returns an evicted order's escrow instead of crediting a fill
+}
+
+// Totals of one incoming order matched against the book
+#[derive(Default)]
+pub struct OrderFill {
+    pub filled: u64,
+    pub taker_collateral: u64, // Paid by a taking bid, or paid out to a taking ask
+    pub maker_collateral: u64, // Credited to filled asks, or released from filled bids
 }
 
+// Each side is a sorted Vec rather than a critbit slab. Anchor deserializes the whole account
+// on every instruction anyway, so with MAX_ORDERS_PER_SIDE = 32 the linear insert and cancel
+// scans stay a small share of that cost, and the account fits in about 5.5 KB without realloc.
+// A side never grows past its capacity: a better price evicts the worst order instead.
+// Deeper books would need a zero-copy critbit tree in place of these Vecs
 #[account]
 #[derive(Default)]
-pub struct Prediction {
-    pub user: Pubkey,
+pub struct OrderBook {
     pub market: Pubkey,
     pub outcome_index: u8,
-    pub amount: u64,
-    pub timestamp: i64,
-    pub claimed: bool,
+    pub next_order_id: u64,
+    pub bids: Vec<Order>,       // Best (highest) price first, then oldest first
+    pub asks: Vec<Order>,       // Best (lowest) price first, then oldest first
+    pub events: Vec<FillEvent>, // Fills waiting for consume_events to credit makers
     pub bump: u8,
 }
 
-impl Prediction {
-    pub const SPACE: usize = 32 + // user
-                            32 + // market
+impl OrderBook {
+    pub const SPACE: usize = 32 + // market
                             1 + // outcome_index
-                            8 + // amount
-                            8 + // timestamp
-                            1 + // claimed
-                            1 + // bump
-                            30; // padding
+                            8 + // next_order_id
+                            4 + MAX_ORDERS_PER_SIDE * (8 + 32 + 8 + 8) + // bids
+                            4 + MAX_ORDERS_PER_SIDE * (8 + 32 + 8 + 8) + // asks
+                            4 + MAX_FILL_EVENTS * (32 + 1 + 8 + 8 + 8 + 1) + // events
+                            1; // bump
+
+    // Fills an incoming order against the opposite side at the makers' prices
+    pub fn match_order(&mut self, side: u8, price: u64, quantity: u64) -> Result<OrderFill> {
+        let mut fill = OrderFill::default();
+        let book = if side == OrderSide::Bid as u8 { &mut self.asks } else { &mut self.bids };
+        
+        while fill.filled < quantity && !book.is_empty() {
+            let maker = &mut book[0];
+            let crosses = if side == OrderSide::Bid as u8 { maker.price <= price } else { maker.price >= price };
+            if !crosses {
+                break;
+            }
+            require!(self.events.len() < MAX_FILL_EVENTS, ErrorCode::EventQueueFull);
+            
+            let traded = maker.quantity.min(quantity.checked_sub(fill.filled).unwrap());
+            let maker_credit = if side == OrderSide::Bid as u8 {
+                fill.taker_collateral = fill.taker_collateral.checked_add(order_collateral(traded, maker.price)).unwrap();
+                (traded as u128 * maker.price as u128 / PRICE_SCALE as u128) as u64
+            } else {
+                fill.taker_collateral = fill.taker_collateral.checked_add((traded as u128 * maker.price as u128 / PRICE_SCALE as u128) as u64).unwrap();
+                order_collateral(maker.quantity, maker.price)
+                    .checked_sub(order_collateral(maker.quantity.checked_sub(traded).unwrap(), maker.price))
+                    .unwrap()
+            };
+            fill.maker_collateral = fill.maker_collateral.checked_add(maker_credit).unwrap();
+            fill.filled = fill.filled.checked_add(traded).unwrap();
+            
+            self.events.push(FillEvent {
+                maker_position: maker.position,
+                maker_side: 1 - side,
+                price: maker.price,
+                quantity: traded,
+                collateral: if side == OrderSide::Bid as u8 { maker_credit } else { 0 },
+                evicted: false,
+            });
+            
+            maker.quantity = maker.quantity.checked_sub(traded).unwrap();
+            if maker.quantity == 0 {
+                book.remove(0);
+            }
+        }
+        
+        Ok(fill)
+    }
+
+    pub fn insert_order(&mut self, side: u8, order: Order) -> Result<()> {
+        let book = if side == OrderSide::Bid as u8 { &mut self.bids } else { &mut self.asks };
+        
+        // A full side makes room by evicting its worst-priced order, so dust orders cannot
+        // lock out better prices. The evicted maker gets its escrow back through the queue
+        if book.len() >= MAX_ORDERS_PER_SIDE {
+            let worst = &book[book.len() - 1];
+            let improves = if side == OrderSide::Bid as u8 { order.price > worst.price } else { order.price < worst.price };
+            require!(improves, ErrorCode::OrderBookFull);
+            require!(self.events.len() < MAX_FILL_EVENTS, ErrorCode::EventQueueFull);
+            
+            let evicted = book.pop().unwrap();
+            self.events.push(FillEvent {
+                maker_position: evicted.position,
+                maker_side: side,
+                price: evicted.price,
+                quantity: evicted.quantity,
+                collateral: if side == OrderSide::Bid as u8 { order_collateral(evicted.quantity, evicted.price) } else { 0 },
+                evicted: true,
+            });
+        }
+        
+        let index = book
+            .iter()
+            .position(|resting| {
+                if side == OrderSide::Bid as u8 { resting.price < order.price } else { resting.price > order.price }
+            })
+            .unwrap_or(book.len());
+        book.insert(index, order);
+        Ok(())
+    }
+
+    pub fn remove_order(&mut self, order_id: u64, position: &Pubkey) -> Result<(u8, Order)> {
+        for (side, book) in [(OrderSide::Bid as u8, &mut self.bids), (OrderSide::Ask as u8, &mut self.asks)] {
+            if let Some(index) = book.iter().position(|order| order.order_id == order_id) {
+                require_keys_eq!(book[index].position, *position, ErrorCode::Unauthorized);
+                return Ok((side, book.remove(index)));
+            }
+        }
+        
+        Err(ErrorCode::OrderNotFound.into())
+    }
 }
 
 #[account]
@@ -1446,10 +4948,11 @@ impl AIResolver {
 }
 
 #[account]
+#[derive(Default)]
 pub struct VoteResult {
     pub market: Pubkey,
-    pub vote_tallies: Vec<u64>,    // Number of votes per outcome
-    pub stake_weights: Vec<u64>,   // Stake-weighted votes per outcome
+    pub vote_tallies: Vec<u64>,    // Number of votes per outcome, then for Invalid
+    pub stake_weights: Vec<u64>,   // Stake-weighted votes per outcome, then for Invalid
     pub vote_count: u64,           // Total number of votes
     pub resolution_proposed: bool, // Whether a resolution has been proposed
     pub proposed_outcome: Option<u8>, // Proposed winning outcome
@@ -1461,8 +4964,8 @@ pub struct VoteResult {
 
 impl VoteResult {
     pub const SPACE: usize = 32 +  // market
-                             4 + 5 * 8 + // vote_tallies (5 outcomes max)
-                             4 + 5 * 8 + // stake_weights (5 outcomes max)
+                             4 + 6 * 8 + // vote_tallies (5 outcomes max, plus Invalid)
+                             4 + 6 * 8 + // stake_weights (5 outcomes max, plus Invalid)
                              8 +  // vote_count
                              1 +  // resolution_proposed
                              1 + 1 + // proposed_outcome (Option<u8>)
@@ -1471,6 +4974,40 @@ impl VoteResult {
                              1 +  // finalized
                              1 +  // bump
                              40;  // padding
+
+    // Votes for INVALID_OUTCOME go in the slot after the market's last outcome
+    pub fn record_vote(&mut self, outcome_index: u8, outcomes: usize, weight: u64) -> Result<()> {
+        let slot = match outcome_index {
+            INVALID_OUTCOME => outcomes,
+            index if (index as usize) < outcomes => index as usize,
+            _ => return err!(ErrorCode::InvalidOutcomeIndex),
+        };
+        require!(slot < self.stake_weights.len(), ErrorCode::InvalidOutcomeIndex);
+        
+        self.vote_tallies[slot] = self.vote_tallies[slot].checked_add(1).unwrap();
+        self.stake_weights[slot] = self.stake_weights[slot].checked_add(weight).unwrap();
+        self.vote_count = self.vote_count.checked_add(1).unwrap();
+        Ok(())
+    }
+
+    // The outcome with the most stake behind it, earliest first on a tie
+    pub fn leading_outcome(&self, outcomes: usize) -> u8 {
+        let mut max_stake = 0;
+        let mut winning_index = 0;
+        
+        for (i, &stake) in self.stake_weights.iter().enumerate() {
+            if stake > max_stake {
+                max_stake = stake;
+                winning_index = i;
+            }
+        }
+        
+        if winning_index >= outcomes {
+            INVALID_OUTCOME
+        } else {
+            winning_index as u8
+        }
+    }
 }
 
 #[account]
@@ -1541,6 +5078,27 @@ impl ProtocolStats {
 pub enum MarketType {
     TimeBound = 0,
     OpenEnded = 1,
+    Binary = 2,
+    Scalar = 3,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum TradingMode {
+    Parimutuel = 0, // Stakes share the pool at resolution
+    Cpmm = 1,       // Outcome shares traded against a constant-product pool
+    Lmsr = 2,       // Outcome shares traded against a creator-subsidized LMSR maker
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum OrderSide {
+    Bid = 0, // Buys shares with escrowed collateral
+    Ask = 1, // Sells escrowed shares
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub enum FeeModel {
+    TotalShare = 0, // Fees taken from the winner's whole share, principal included
+    ProfitOnly = 1, // Fees taken only from winnings paid out of the losing pool
 }
 
 // Helper function for tier management
@@ -1568,6 +5126,222 @@ pub fn get_creator_fee_bps(tier: u8) -> u16 {
     }
 }
 
+// Helper function to compute (creator_fee, protocol_fee) on a winner's share
+pub fn calculate_fees(
+    user_share: u128,
+    user_stake: u64,
+    creator_fee_bps: u16,
+    protocol_fee_bps: u16,
+    fee_model: u8,
+) -> (u128, u128) {
+    let fee_base = if fee_model == FeeModel::ProfitOnly as u8 {
+        user_share.saturating_sub(user_stake as u128)
+    } else {
+        user_share
+    };
+    
+    let creator_fee = fee_base
+        .checked_mul(creator_fee_bps as u128)
+        .unwrap()
+        .checked_div(10000)
+        .unwrap();
+    
+    let protocol_fee = fee_base
+        .checked_mul(protocol_fee_bps as u128)
+        .unwrap()
+        .checked_div(10000)
+        .unwrap();
+    
+    (creator_fee, protocol_fee)
+}
+
+// Helper function for CPMM buys: shares of `outcome_index` released when
+// `investment` complete sets are added to the pool, keeping prod(reserves) fixed
+pub fn cpmm_buy_shares(reserves: &[u64], outcome_index: usize, investment: u64) -> Option<u64> {
+    let investment = investment as u128;
+    let mut ending_reserve = reserves[outcome_index] as u128;
+    
+    for (j, reserve) in reserves.iter().enumerate() {
+        if j != outcome_index {
+            let reserve = *reserve as u128;
+            ending_reserve = ending_reserve
+                .checked_mul(reserve)?
+                .div_ceil(reserve.checked_add(investment)?);
+        }
+    }
+    
+    let shares = (reserves[outcome_index] as u128 + investment).checked_sub(ending_reserve)?;
+    u64::try_from(shares).ok()
+}
+
+// Helper function for CPMM sells: shares of `outcome_index` the pool must take
+// in to release `return_amount` complete sets, keeping prod(reserves) fixed
+pub fn cpmm_sell_shares(reserves: &[u64], outcome_index: usize, return_amount: u64) -> Option<u64> {
+    let return_amount = return_amount as u128;
+    let mut ending_reserve = reserves[outcome_index] as u128;
+    
+    for (j, reserve) in reserves.iter().enumerate() {
+        let reserve = *reserve as u128;
+        if reserve <= return_amount {
+            return None;
+        }
+        
+        if j != outcome_index {
+            ending_reserve = ending_reserve
+                .checked_mul(reserve)?
+                .div_ceil(reserve - return_amount);
+        }
+    }
+    
+    let shares = ending_reserve.checked_add(return_amount)?.checked_sub(reserves[outcome_index] as u128)?;
+    u64::try_from(shares).ok()
+}
+
+// Helper function for the collateral locked by a bid, rounded up in the book's favour
+pub fn order_collateral(quantity: u64, price: u64) -> u64 {
+    (quantity as u128 * price as u128).div_ceil(PRICE_SCALE as u128) as u64
+}
+
+// Helper function for CPMM prices: each outcome is priced in proportion to 1 / reserve
+pub fn cpmm_prices(reserves: &[u64]) -> Option<Vec<u64>> {
+    // Large enough that 1 / reserve keeps its precision for any u64 reserve
+    let unit = 10u128.pow(30);
+    let inverses = reserves
+        .iter()
+        .map(|reserve| unit.checked_div(*reserve as u128))
+        .collect::<Option<Vec<u128>>>()?;
+    let total = inverses.iter().sum::<u128>();
+    
+    Some(inverses.iter().map(|inverse| (inverse * PRICE_SCALE as u128 / total) as u64).collect())
+}
+
+// Helper function for fixed-point e^x, with x and the result scaled by FP_SCALE.
+// Splits x = k * ln(2) + r and sums the Taylor series of e^r for |r| <= ln(2) / 2.
+pub fn fp_exp(x: i128) -> Option<u128> {
+    let scale = FP_SCALE as i128;
+    if x > 40 * scale {
+        return None;
+    }
+    if x < -40 * scale {
+        return Some(0);
+    }
+    
+    let k = if x >= 0 { (x + FP_LN_2 / 2) / FP_LN_2 } else { (x - FP_LN_2 / 2) / FP_LN_2 };
+    let r = x - k * FP_LN_2;
+    
+    let mut sum = scale;
+    let mut term = scale;
+    for i in 1..30 {
+        term = term * r / (i * scale);
+        if term == 0 {
+            break;
+        }
+        sum += term;
+    }
+    
+    let sum = sum as u128;
+    Some(if k >= 0 { sum << k } else { sum >> -k })
+}
+
+// Helper function for fixed-point ln(x), with x and the result scaled by FP_SCALE.
+// Normalizes x = m * 2^k with m in [1, 2) and sums ln(m) = 2 * atanh((m - 1) / (m + 1)).
+pub fn fp_ln(x: u128) -> Option<i128> {
+    if x == 0 {
+        return None;
+    }
+    
+    let mut k: i128 = 0;
+    let mut m = x;
+    while m >= 2 * FP_SCALE {
+        m >>= 1;
+        k += 1;
+    }
+    while m < FP_SCALE {
+        m <<= 1;
+        k -= 1;
+    }
+    
+    let scale = FP_SCALE as i128;
+    let s = ((m - FP_SCALE) * FP_SCALE / (m + FP_SCALE)) as i128;
+    let s_squared = s * s / scale;
+    
+    let mut sum = 0;
+    let mut term = s;
+    let mut i = 1;
+    while term != 0 {
+        sum += term / i;
+        term = term * s_squared / scale;
+        i += 2;
+    }
+    
+    Some(2 * sum + k * FP_LN_2)
+}
+
+// Helper function for the LMSR weights e^((q_j - max q) / b), scaled by FP_SCALE
+fn lmsr_weights(shares: &[u64], b: u64) -> Option<Vec<u128>> {
+    let max_shares = shares.iter().copied().max()?;
+    
+    shares
+        .iter()
+        .map(|q| {
+            let x = ((max_shares - q) as u128).checked_mul(FP_SCALE)? / (b as u128);
+            fp_exp(-i128::try_from(x).ok()?)
+        })
+        .collect()
+}
+
+// Helper function for the LMSR subsidy b * ln(n), the market maker's worst-case loss
+pub fn lmsr_subsidy(b: u64, outcomes: usize) -> Option<u64> {
+    let ln_n = fp_ln(outcomes as u128 * FP_SCALE)? as u128;
+    u64::try_from((b as u128).checked_mul(ln_n)?.div_ceil(FP_SCALE)).ok()
+}
+
+// Helper function for LMSR prices: the softmax of q / b
+pub fn lmsr_prices(shares: &[u64], b: u64) -> Option<Vec<u64>> {
+    let weights = lmsr_weights(shares, b)?;
+    let total = weights.iter().sum::<u128>();
+    
+    weights
+        .iter()
+        .map(|weight| Some((weight.checked_mul(PRICE_SCALE as u128)? / total) as u64))
+        .collect()
+}
+
+// Helper function for LMSR buys: shares of `outcome_index` that `amount` buys, so
+// that the cost function C(q) = b * ln(sum e^(q_j / b)) rises by exactly `amount`
+pub fn lmsr_buy_shares(shares: &[u64], b: u64, outcome_index: usize, amount: u64) -> Option<u64> {
+    let weights = lmsr_weights(shares, b)?;
+    let total = weights.iter().sum::<u128>();
+    
+    let growth = fp_exp(i128::try_from((amount as u128) * FP_SCALE / (b as u128)).ok()?)?;
+    let grown_total = total.checked_mul(growth)? / FP_SCALE;
+    let grown_weight = weights[outcome_index].checked_add(grown_total - total)?;
+    
+    let ratio = grown_weight.checked_mul(FP_SCALE)?.checked_div(weights[outcome_index])?;
+    let bought = (b as u128).checked_mul(fp_ln(ratio)? as u128)? / FP_SCALE;
+    
+    u64::try_from(bought).ok()
+}
+
+// Helper function for LMSR sells: shares of `outcome_index` the maker must take back
+// so that the cost function falls by `return_amount`, rounded in the maker's favour
+pub fn lmsr_sell_shares(shares: &[u64], b: u64, outcome_index: usize, return_amount: u64) -> Option<u64> {
+    let weights = lmsr_weights(shares, b)?;
+    let total = weights.iter().sum::<u128>();
+    
+    let shrink = fp_exp(-i128::try_from((return_amount as u128) * FP_SCALE / (b as u128)).ok()?)?;
+    let shrunk_total = total * shrink / FP_SCALE;
+    let shrunk_weight = weights[outcome_index].checked_sub(total - shrunk_total)?;
+    if shrunk_weight == 0 {
+        return None;
+    }
+    
+    let ratio = weights[outcome_index].checked_mul(FP_SCALE)?.div_ceil(shrunk_weight);
+    let sold = (b as u128).checked_mul(fp_ln(ratio)? as u128)?.div_ceil(FP_SCALE);
+    
+    u64::try_from(sold).ok()
+}
+
 #[error_code]
 pub enum ErrorCode {
     #[msg("Too many outcomes. Maximum is 5.")]
@@ -1609,7 +5383,7 @@ pub enum ErrorCode {
     #[msg("Unauthorized action.")]
     Unauthorized,
     
-    #[msg("Winning outcome index required for time-bound markets.")]
+    #[msg("Winning outcome index required for time-bound and binary markets.")]
     WinningOutcomeRequired,
     
     #[msg("Market not resolved yet.")]
@@ -1639,7 +5413,7 @@ pub enum ErrorCode {
     #[msg("Market has not yet reached its deadline.")]
     MarketNotExpired,
     
-    #[msg("AI resolver can only resolve time-bound markets.")]
+    #[msg("AI resolver can only resolve time-bound or binary markets.")]
     NotTimeBoundMarket,
     
     #[msg("Invalid weight for vote authority. Must be between 1 and 5.")]
@@ -1662,4 +5436,934 @@ pub enum ErrorCode {
     
     #[msg("Resolution has already been finalized.")]
     ResolutionFinalized,
-}
\ No newline at end of file
+    
+    #[msg("Amount must be greater than zero.")]
+    InvalidAmount,
+    
+    #[msg("Protocol treasury balance is insufficient.")]
+    InsufficientTreasuryBalance,
+    
+    #[msg("Protocol treasury account is not owned by the treasury authority.")]
+    InvalidTreasury,
+    
+    #[msg("No creator fees to withdraw.")]
+    NoCreatorFees,
+    
+    #[msg("Invalid fee model.")]
+    InvalidFeeModel,
+    
+    #[msg("Market vault has already been swept.")]
+    MarketVaultSwept,
+    
+    #[msg("Winning predictions are still within the claim period.")]
+    ClaimPeriodActive,
+    
+    #[msg("Market vault still holds tokens.")]
+    MarketVaultNotEmpty,
+    
+    #[msg("Prediction is on the winning outcome; claim it instead.")]
+    NotLosingPrediction,
+    
+    #[msg("Not a binary market.")]
+    NotBinaryMarket,
+    
+    #[msg("Binary and scalar markets use implicit outcomes; pass an empty outcome list.")]
+    ImplicitOutcomes,
+    
+    #[msg("Scalar markets need a lower bound strictly below the upper bound.")]
+    InvalidScalarBounds,
+    
+    #[msg("Not a scalar market.")]
+    NotScalarMarket,
+    
+    #[msg("Scalar markets resolve to a value, not an outcome index.")]
+    ScalarValueRequired,
+    
+    #[msg("Payout vector needs one numerator per outcome and at least one non-zero entry.")]
+    InvalidPayoutVector,
+    
+    #[msg("Invalid trading mode.")]
+    InvalidTradingMode,
+    
+    #[msg("Stakes are only accepted on parimutuel markets.")]
+    NotParimutuelMarket,
+    
+    #[msg("Not an AMM market.")]
+    NotAmmMarket,
+    
+    #[msg("Not enough liquidity in the pool for this trade.")]
+    InsufficientLiquidity,
+    
+    #[msg("Price moved beyond the allowed slippage.")]
+    SlippageExceeded,
+    
+    #[msg("Not enough outcome shares in the position.")]
+    InsufficientShares,
+    
+    #[msg("Not enough LP shares in the position.")]
+    InsufficientLpShares,
+    
+    #[msg("Position holds no shares with a payout.")]
+    NoSharesToRedeem,
+    
+    #[msg("LMSR markets need a liquidity parameter and a creator token account to fund the subsidy.")]
+    LmsrLiquidityRequired,
+    
+    #[msg("Outcome mint already initialized.")]
+    OutcomeMintAlreadyInitialized,
+    
+    #[msg("Mint is not the initialized mint of this outcome.")]
+    InvalidOutcomeMint,
+    
+    #[msg("No outcome tokens to claim.")]
+    NoOutcomeTokens,
+    
+    #[msg("Orders need a side, a price strictly between 0 and 1, and a quantity.")]
+    InvalidOrder,
+    
+    #[msg("This side of the order book is full.")]
+    OrderBookFull,
+    
+    #[msg("Fill event queue is full; run consume_events first.")]
+    EventQueueFull,
+    
+    #[msg("Order not found.")]
+    OrderNotFound,
+    
+    #[msg("No settled funds to withdraw.")]
+    NoFundsToSettle,
+    
+    #[msg("Token account does not hold this prediction's position NFT.")]
+    InvalidPositionMint,
+    
+    #[msg("Winning position NFT has not been claimed yet.")]
+    PositionNftOutstanding,
+    
+    #[msg("A prediction cannot be transferred to its current owner.")]
+    InvalidTransferRecipient,
+    
+    #[msg("Native SOL markets only support parimutuel outcome-token staking.")]
+    NativeSolUnsupported,
+    
+    #[msg("Native SOL markets must be created with the native mint.")]
+    InvalidNativeMint,
+    
+    #[msg("This market holds native SOL and needs its SOL vault and the system program.")]
+    SolVaultRequired,
+    
+    #[msg("A token account for the market's collateral mint is required.")]
+    CollateralAccountRequired,
+    
+    #[msg("Share trading markets only support SPL Token collateral.")]
+    Token2022Unsupported,
+    
+    #[msg("This collateral mint is not enabled for new markets.")]
+    CollateralMintDisabled,
+    
+    #[msg("Stake is below the market's minimum.")]
+    StakeBelowMinimum,
+    
+    #[msg("Stake is above the market's per-transaction maximum.")]
+    StakeAboveMaximum,
+    
+    #[msg("Stake would take the position above the market's per-user cap.")]
+    PositionCapExceeded,
+    
+    #[msg("Stake limits must be non-zero, respect the collateral minimum and have max >= min.")]
+    InvalidStakeLimits,
+    
+    #[msg("batch_stake needs one group of accounts per entry, in entry order.")]
+    BatchAccountsMismatch,
+    
+    #[msg("Share trading markets resolve to a single outcome, not a payout vector.")]
+    PayoutVectorUnsupported,
+    
+    #[msg("A batch can stake on each market only once.")]
+    DuplicateBatchMarket,
+    
+    #[msg("A prediction can only be topped up on its own outcome, and not when it has a position NFT.")]
+    InvalidTopUp,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_market(creator: Pubkey, market_index: u32, outcomes: usize) -> Market {
+        let (_, bump) = Pubkey::find_program_address(
+            &[b"market", creator.as_ref(), &market_index.to_le_bytes()],
+            &crate::ID,
+        );
+        
+        Market {
+            creator,
+            market_index,
+            outcomes: (0..outcomes).map(|i| format!("Outcome {}", i)).collect(),
+            creator_fee_bps: get_creator_fee_bps(0),
+            protocol_fee_bps: 50,
+            fee_model: FeeModel::ProfitOnly as u8,
+            stakes_per_outcome: vec![0; outcomes],
+            bump,
+            ..Market::default()
+        }
+    }
+
+    #[test]
+    fn outcome_share_includes_the_new_stake() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        assert_eq!(market.outcome_share_bps(0, 0), 0);
+        assert_eq!(market.outcome_share_bps(0, 100), 10000);
+        
+        market.record_stake(0, 300);
+        market.record_stake(1, 600);
+        assert_eq!(market.outcome_share_bps(0, 100), 4000);
+        assert_eq!(market.outcome_share_bps(1, 100), 7000);
+        
+        // A whale landing first worsens the quote a smaller staker computed
+        market.record_stake(0, 9_000);
+        assert!(market.outcome_share_bps(0, 100) > 4000);
+    }
+
+    #[test]
+    fn stake_limits_respect_the_collateral_minimum() {
+        assert_eq!(StakeLimits::default().resolve(0).unwrap(), (1, u64::MAX, u64::MAX));
+        assert_eq!(StakeLimits::default().resolve(500).unwrap(), (500, u64::MAX, u64::MAX));
+        
+        let limits = StakeLimits {
+            min_stake: Some(1_000),
+            max_stake: Some(50_000),
+            max_position: Some(100_000),
+        };
+        assert_eq!(limits.resolve(500).unwrap(), (1_000, 50_000, 100_000));
+        
+        // Below the registered minimum, zero, or with max under min
+        assert!(limits.resolve(2_000).is_err());
+        assert!(StakeLimits { min_stake: Some(0), ..StakeLimits::default() }.resolve(0).is_err());
+        assert!(StakeLimits { max_stake: Some(10), ..limits.clone() }.resolve(0).is_err());
+        assert!(StakeLimits { max_position: Some(10), ..limits.clone() }.resolve(0).is_err());
+    }
+
+    #[test]
+    fn signer_seeds_match_market_pda() {
+        let creator = Pubkey::new_unique();
+        
+        for market_index in [0u32, 1, 7, 300] {
+            let market = new_market(creator, market_index, 2);
+            let (expected, _) = Pubkey::find_program_address(
+                &[b"market", creator.as_ref(), &market_index.to_le_bytes()],
+                &crate::ID,
+            );
+            
+            let index_bytes = market.market_index.to_le_bytes();
+            let seeds = market.signer_seeds(&index_bytes);
+            let signer = Pubkey::create_program_address(&seeds, &crate::ID).unwrap();
+            
+            assert_eq!(signer, expected);
+        }
+    }
+
+    #[test]
+    fn stake_resolve_claim_round_trip() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        let winners = [100_000_003u64, 50_000_001];
+        let losers = [300_000_007u64];
+        
+        for amount in winners {
+            market.record_stake(0, amount);
+        }
+        for amount in losers {
+            market.record_stake(1, amount);
+        }
+        
+        let vault = winners.iter().chain(losers.iter()).sum::<u64>();
+        assert_eq!(market.total_pool, vault);
+        assert_eq!(market.stakes_per_outcome, vec![150_000_004, 300_000_007]);
+        
+        market.set_winning_outcome(Some(0)).unwrap();
+        market.resolved = true;
+        
+        let mut paid_out = 0u64;
+        let mut rewards = 0u64;
+        let mut creator_fees = 0u64;
+        for amount in winners {
+            let (reward, creator_fee, protocol_fee) = market.settle_claim(0, amount).unwrap();
+            assert!(reward >= amount, "winner received less than principal");
+            paid_out += reward + creator_fee + protocol_fee;
+            rewards += reward;
+            creator_fees += creator_fee;
+        }
+        assert!(market.settle_claim(1, losers[0]).is_err());
+        
+        assert_eq!(market.claimed_winning_stake, market.stakes_per_outcome[0]);
+        assert_eq!(market.total_claimed, rewards);
+        assert_eq!(market.creator_fees_accrued, creator_fees);
+        assert!(!market.has_unclaimed_payouts());
+        assert!(paid_out <= vault);
+        // Only rounding dust is left behind for sweep_market_vault
+        assert!(vault - paid_out < winners.len() as u64 * 2);
+    }
+
+    #[test]
+    fn winning_share_requires_winning_stakes() {
+        let mut market = new_market(Pubkey::new_unique(), 3, 3);
+        market.record_stake(1, 1_000);
+        market.set_winning_outcome(Some(0)).unwrap();
+        market.resolved = true;
+        
+        assert!(market.winning_share(0, 1_000).is_err());
+    }
+
+    #[test]
+    fn scalar_payout_is_linear_in_resolved_value() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.market_type = MarketType::Scalar as u8;
+        market.scalar_lower = -100;
+        market.scalar_upper = 300;
+        market.record_stake(SCALAR_LONG_INDEX, 600);
+        market.record_stake(SCALAR_SHORT_INDEX, 400);
+        
+        // 25% of the way through the range: LONG gets a quarter of the pool
+        market.set_scalar_value(0);
+        assert_eq!(market.winning_share(SCALAR_LONG_INDEX, 600).unwrap(), 250);
+        assert_eq!(market.winning_share(SCALAR_SHORT_INDEX, 400).unwrap(), 750);
+        assert_eq!(market.claimable_stake(), 1_000);
+        
+        // Values outside the range are clamped to the bounds
+        market.set_scalar_value(10_000);
+        assert_eq!(market.winning_share(SCALAR_LONG_INDEX, 300).unwrap(), 500);
+        assert!(!market.is_winning_outcome(SCALAR_SHORT_INDEX).unwrap());
+        assert_eq!(market.claimable_stake(), 600);
+    }
+
+    #[test]
+    fn payout_vector_splits_pool_between_outcomes() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 3);
+        market.record_stake(0, 100);
+        market.record_stake(1, 300);
+        market.record_stake(2, 600);
+        
+        // A tie between outcomes 0 and 1: each side gets half of the pool
+        market.set_payout_numerators(vec![1, 1, 0]).unwrap();
+        assert_eq!(market.winning_share(0, 100).unwrap(), 500);
+        assert_eq!(market.winning_share(1, 150).unwrap(), 250);
+        assert!(!market.is_winning_outcome(2).unwrap());
+        assert_eq!(market.claimable_stake(), 400);
+        
+        assert!(market.set_payout_numerators(vec![0, 0, 0]).is_err());
+        assert!(market.set_payout_numerators(vec![1, 1]).is_err());
+        
+        let mut scalar = new_market(Pubkey::new_unique(), 1, 2);
+        scalar.market_type = MarketType::Scalar as u8;
+        assert!(scalar.set_payout_numerators(vec![1, 1]).is_err());
+        
+        let mut amm = new_market(Pubkey::new_unique(), 2, 2);
+        amm.trading_mode = TradingMode::Cpmm as u8;
+        assert!(amm.set_payout_numerators(vec![1, 1]).is_err());
+    }
+
+    #[test]
+    fn invalid_resolution_refunds_every_stake() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 3);
+        market.record_stake(0, 123);
+        market.record_stake(1, 4_567);
+        market.record_stake(1, 89);
+        
+        market.set_winning_outcome(Some(INVALID_OUTCOME)).unwrap();
+        assert!(market.invalid);
+        assert_eq!(market.winning_outcome, None);
+        assert_eq!(market.winning_share(0, 123).unwrap(), 123);
+        assert_eq!(market.winning_share(1, 4_567).unwrap(), 4_567);
+        assert_eq!(market.winning_share(1, 89).unwrap(), 89);
+        assert!(!market.is_winning_outcome(2).unwrap());
+        assert_eq!(market.claimable_stake(), market.total_pool);
+        
+        let mut open_ended = new_market(Pubkey::new_unique(), 1, 2);
+        open_ended.record_stake(0, 10);
+        open_ended.set_winning_outcome(None).unwrap();
+        assert!(open_ended.invalid);
+        assert_eq!(open_ended.winning_share(0, 10).unwrap(), 10);
+    }
+
+    #[test]
+    fn stake_weighted_votes_can_choose_invalid() {
+        let mut vote_result = VoteResult {
+            vote_tallies: vec![0; 3],
+            stake_weights: vec![0; 3],
+            ..VoteResult::default()
+        };
+        
+        vote_result.record_vote(0, 2, 400).unwrap();
+        vote_result.record_vote(INVALID_OUTCOME, 2, 300).unwrap();
+        assert_eq!(vote_result.leading_outcome(2), 0);
+        
+        vote_result.record_vote(INVALID_OUTCOME, 2, 200).unwrap();
+        assert_eq!(vote_result.stake_weights, vec![400, 0, 500]);
+        assert_eq!(vote_result.vote_tallies, vec![1, 0, 2]);
+        assert_eq!(vote_result.leading_outcome(2), INVALID_OUTCOME);
+        
+        assert!(vote_result.record_vote(2, 2, 1).is_err());
+    }
+
+    #[test]
+    fn unstaked_paid_outcome_does_not_strand_funds() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.record_stake(0, 400);
+        
+        market.set_payout_numerators(vec![1, 3]).unwrap();
+        assert_eq!(market.winning_share(0, 400).unwrap(), 400);
+    }
+
+    #[test]
+    fn outcome_mints_resolve_to_their_outcome() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 3);
+        market.outcome_mints = vec![Pubkey::default(); 3];
+        
+        let mint = Pubkey::new_unique();
+        market.outcome_mints[2] = mint;
+        
+        assert_eq!(market.outcome_index_for_mint(&mint).unwrap(), 2);
+        assert!(market.outcome_index_for_mint(&Pubkey::new_unique()).is_err());
+        assert!(market.outcome_index_for_mint(&Pubkey::default()).is_err());
+    }
+
+    #[test]
+    fn total_share_fee_model_charges_principal() {
+        let user_share = 1_000_000u128;
+        let (profit_creator, profit_protocol) =
+            calculate_fees(user_share, 900_000, 150, 50, FeeModel::ProfitOnly as u8);
+        let (total_creator, total_protocol) =
+            calculate_fees(user_share, 900_000, 150, 50, FeeModel::TotalShare as u8);
+        
+        assert_eq!((profit_creator, profit_protocol), (1_500, 500));
+        assert_eq!((total_creator, total_protocol), (15_000, 5_000));
+    }
+
+    #[test]
+    fn cpmm_trades_keep_the_reserve_product() {
+        let reserves = [1_000_000u64, 1_000_000, 1_000_000];
+        let product = |r: &[u64]| r.iter().map(|x| *x as u128).product::<u128>();
+        
+        let bought = cpmm_buy_shares(&reserves, 0, 100_000).unwrap();
+        let after_buy = [1_100_000 - bought, 1_100_000, 1_100_000];
+        assert!(bought > 100_000);
+        assert!(product(&after_buy) >= product(&reserves));
+        
+        // Selling back the same collateral costs at least the shares just bought
+        let sold = cpmm_sell_shares(&after_buy, 0, 100_000).unwrap();
+        let after_sell = [after_buy[0] - 100_000 + sold, 1_000_000, 1_000_000];
+        assert!(sold >= bought);
+        assert!(product(&after_sell) >= product(&reserves));
+        
+        assert_eq!(cpmm_sell_shares(&reserves, 1, 1_000_000), None);
+    }
+
+    #[test]
+    fn share_market_redeems_complete_sets_at_par() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.trading_mode = TradingMode::Cpmm as u8;
+        market.outstanding_shares = vec![300, 100];
+        
+        market.set_winning_outcome(Some(0)).unwrap();
+        assert_eq!(market.share_payout(&[300, 100]).unwrap(), 300);
+        assert!(market.has_unclaimed_payouts());
+        
+        let mut invalid = new_market(Pubkey::new_unique(), 1, 2);
+        invalid.trading_mode = TradingMode::Cpmm as u8;
+        invalid.set_winning_outcome(Some(INVALID_OUTCOME)).unwrap();
+        assert_eq!(invalid.share_payout(&[50, 50]).unwrap(), 50);
+        assert_eq!(invalid.share_payout(&[81, 0]).unwrap(), 40);
+        assert!(!invalid.has_unclaimed_payouts());
+    }
+
+    #[test]
+    fn complete_sets_keep_pool_and_supply_in_step() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 3);
+        market.trading_mode = TradingMode::Cpmm as u8;
+        market.amm_reserves = vec![1_000, 1_000, 1_000];
+        market.outstanding_shares = vec![0, 0, 0];
+        market.total_pool = 1_000;
+        market.lp_total_shares = 1_000;
+        
+        let invariant = |m: &Market| {
+            (0..3).all(|j| m.amm_reserves[j] + m.outstanding_shares[j] == m.total_pool)
+        };
+        
+        market.apply_buy(1, 200).unwrap();
+        market.mint_complete_sets(500);
+        assert!(invariant(&market));
+        
+        market.burn_complete_sets(400).unwrap();
+        assert!(invariant(&market));
+        assert!(market.burn_complete_sets(200).is_err());
+        
+        // A complete set always redeems for exactly one unit after resolution
+        let sets = market.outstanding_shares[0];
+        market.set_winning_outcome(Some(2)).unwrap();
+        assert_eq!(market.share_payout(&[sets, sets, sets]).unwrap(), sets);
+    }
+
+    #[test]
+    fn order_book_matches_at_maker_prices_in_time_priority() {
+        let mut book = OrderBook::default();
+        let (alice, bob, carol) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let order = |order_id, position, price, quantity| Order { order_id, position, price, quantity };
+        
+        book.insert_order(OrderSide::Ask as u8, order(0, alice, 600_000, 100)).unwrap();
+        book.insert_order(OrderSide::Ask as u8, order(1, bob, 550_000, 50)).unwrap();
+        book.insert_order(OrderSide::Ask as u8, order(2, carol, 600_000, 100)).unwrap();
+        assert_eq!(book.asks.iter().map(|o| o.order_id).collect::<Vec<_>>(), vec![1, 0, 2]);
+        
+        // A bid at 0.60 for 120 takes Bob's 50 at 0.55, then 70 of Alice's at 0.60
+        let fill = book.match_order(OrderSide::Bid as u8, 600_000, 120).unwrap();
+        assert_eq!(fill.filled, 120);
+        assert_eq!(fill.taker_collateral, 28 + 42);
+        assert_eq!(fill.maker_collateral, 27 + 42);
+        assert_eq!(book.events.len(), 2);
+        assert_eq!(book.events[0].maker_position, bob);
+        assert_eq!(book.asks[0].quantity, 30);
+        
+        // Bids escrow rounded up; fills never release more than was escrowed
+        book.insert_order(OrderSide::Bid as u8, order(3, carol, 333_333, 10)).unwrap();
+        let escrowed = order_collateral(10, 333_333);
+        let mut released = 0;
+        let mut paid = 0;
+        for _ in 0..3 {
+            let fill = book.match_order(OrderSide::Ask as u8, 300_000, 3).unwrap();
+            released += fill.maker_collateral;
+            paid += fill.taker_collateral;
+        }
+        let refund = order_collateral(book.bids[0].quantity, 333_333);
+        assert!(paid <= released);
+        assert_eq!(released + refund, escrowed);
+        
+        assert!(book.remove_order(3, &alice).is_err());
+        assert_eq!(book.remove_order(3, &carol).unwrap().1.quantity, 1);
+        assert!(book.remove_order(3, &carol).is_err());
+    }
+
+    #[test]
+    fn full_order_book_evicts_the_worst_price() {
+        let mut book = OrderBook::default();
+        let spammer = Pubkey::new_unique();
+        let order = |order_id, position, price, quantity| Order { order_id, position, price, quantity };
+        
+        for order_id in 0..MAX_ORDERS_PER_SIDE as u64 {
+            book.insert_order(OrderSide::Bid as u8, order(order_id, spammer, 1 + order_id, 1)).unwrap();
+        }
+        
+        // A bid no better than the worst resting one is still refused
+        let maker = Pubkey::new_unique();
+        assert!(book.insert_order(OrderSide::Bid as u8, order(100, maker, 1, 500)).is_err());
+        
+        book.insert_order(OrderSide::Bid as u8, order(101, maker, 400_000, 500)).unwrap();
+        assert_eq!(book.bids.len(), MAX_ORDERS_PER_SIDE);
+        assert_eq!(book.bids[0].order_id, 101);
+        assert!(book.bids.iter().all(|o| o.order_id != 0));
+        
+        let event = &book.events[0];
+        assert!(event.evicted);
+        assert_eq!(event.maker_position, spammer);
+        assert_eq!(event.collateral, order_collateral(1, 1));
+    }
+
+    #[test]
+    fn fixed_point_exp_and_ln_are_accurate() {
+        let scale = FP_SCALE as f64;
+        
+        for x in [-20.0f64, -3.5, -0.25, 0.0, 0.5, 1.0, 7.25, 25.0] {
+            let got = fp_exp((x * scale) as i128).unwrap() as f64 / scale;
+            assert!((got - x.exp()).abs() <= x.exp() * 1e-9 + 1e-12, "exp({})", x);
+        }
+        
+        for x in [0.001f64, 0.5, 1.0, 2.0, 3.0, 5.0, 1234.5] {
+            let got = fp_ln((x * scale) as u128).unwrap() as f64 / scale;
+            assert!((got - x.ln()).abs() <= 1e-9, "ln({})", x);
+        }
+    }
+
+    #[test]
+    fn lmsr_trades_follow_the_cost_function() {
+        let b = 1_000_000u64;
+        let subsidy = lmsr_subsidy(b, 2).unwrap();
+        assert_eq!(subsidy, 693_148);
+        
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.trading_mode = TradingMode::Lmsr as u8;
+        market.lmsr_liquidity = b;
+        market.outstanding_shares = vec![0, 0];
+        market.total_pool = subsidy;
+        assert_eq!(market.share_prices().unwrap(), vec![500_000, 500_000]);
+        
+        // Cost of q = [x, 0] from q = [0, 0] is b * ln((e^(x/b) + 1) / 2)
+        let (bought, fee) = market.apply_buy(0, 500_000).unwrap();
+        let expected = (b as f64) * ((2.0 * (0.5f64).exp()) - 1.0).ln();
+        assert_eq!(fee, 0);
+        assert!((bought as f64 - expected).abs() <= 1.0);
+        
+        let prices = market.share_prices().unwrap();
+        assert!(prices[0] > 500_000 && prices[1] < 500_000);
+        assert!(prices[0] + prices[1] >= PRICE_SCALE - 1);
+        
+        // Rounding favours the maker, so a full round trip returns slightly less
+        assert!(market.apply_sell(0, 500_000).is_err());
+        let (sold, _) = market.apply_sell(0, 499_999).unwrap();
+        assert!(sold <= bought && sold + 2 >= bought);
+        assert!(market.total_pool >= *market.outstanding_shares.iter().max().unwrap());
+        
+        market.outstanding_shares = vec![bought, 0];
+        market.total_pool = subsidy + 500_000;
+        market.set_winning_outcome(Some(1)).unwrap();
+        market.resolved = true;
+        assert_eq!(market.lmsr_unused_subsidy().unwrap(), subsidy + 500_000);
+    }
+
+    #[test]
+    fn sweep_leaves_the_unused_lmsr_subsidy_for_the_creator() {
+        let b = 1_000_000u64;
+        let subsidy = lmsr_subsidy(b, 2).unwrap();
+        
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.trading_mode = TradingMode::Lmsr as u8;
+        market.lmsr_liquidity = b;
+        market.outstanding_shares = vec![0, 0];
+        market.total_pool = subsidy;
+        let (bought, _) = market.apply_buy(0, 400_000).unwrap();
+        
+        market.set_winning_outcome(Some(0)).unwrap();
+        market.resolved = true;
+        let unused_subsidy = market.lmsr_unused_subsidy().unwrap();
+        assert_eq!(unused_subsidy, subsidy + 400_000 - bought);
+        
+        // Every winning share is redeemed, leaving only the subsidy in the vault
+        market.outstanding_shares = vec![0, 0];
+        market.total_claimed = bought;
+        assert!(!market.has_unclaimed_payouts());
+        let vault_balance = market.total_pool - market.total_claimed;
+        
+        assert_eq!(market.sweep(vault_balance).unwrap(), 0);
+        assert!(market.swept);
+        assert_eq!(market.creator_fees_accrued, unused_subsidy);
+        assert!(market.sweep(vault_balance).is_err());
+    }
+
+    // Leaked so the accounts live for the 'info lifetime the program's loaders expect
+    fn account_info(key: Pubkey, owner: Pubkey, lamports: u64, data: Vec<u8>) -> AccountInfo<'static> {
+        AccountInfo::new(
+            Box::leak(Box::new(key)),
+            false,
+            true,
+            Box::leak(Box::new(lamports)),
+            Box::leak(data.into_boxed_slice()),
+            Box::leak(Box::new(owner)),
+            false,
+            0,
+        )
+    }
+
+    fn program_account<T: AccountSerialize>(key: Pubkey, account: &T) -> AccountInfo<'static> {
+        let mut data = Vec::new();
+        account.try_serialize(&mut data).unwrap();
+        account_info(key, crate::ID, 1, data)
+    }
+
+    fn token_account(key: Pubkey, mint: Pubkey, owner: Pubkey) -> AccountInfo<'static> {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token::spl_token::state::{Account as SplAccount, AccountState};
+        
+        let mut data = vec![0; SplAccount::LEN];
+        let account = SplAccount { mint, owner, state: AccountState::Initialized, ..SplAccount::default() };
+        SplAccount::pack(account, &mut data).unwrap();
+        account_info(key, token::ID, 1, data)
+    }
+
+    fn mint_account(key: Pubkey) -> AccountInfo<'static> {
+        use anchor_lang::solana_program::program_pack::Pack;
+        use anchor_spl::token::spl_token::state::Mint as SplMint;
+        
+        let mut data = vec![0; SplMint::LEN];
+        SplMint::pack(SplMint { decimals: 6, is_initialized: true, ..SplMint::default() }, &mut data).unwrap();
+        account_info(key, token::ID, 1, data)
+    }
+
+    fn pda(seeds: &[&[u8]]) -> Pubkey {
+        Pubkey::find_program_address(seeds, &crate::ID).0
+    }
+
+    // A well-formed batch_stake group for `beneficiary`, in StakePrediction's account order
+    fn batch_group(market: &Market, market_key: Pubkey, beneficiary: Pubkey) -> Vec<AccountInfo<'static>> {
+        let collateral_mint = Pubkey::new_unique();
+        let outcome_mint = Pubkey::new_unique();
+        let market_vault = pda(&[b"market_vault", market_key.as_ref()]);
+        let creator_profile = CreatorProfile { creator: market.creator, ..CreatorProfile::default() };
+        
+        let collateral = if market.native_sol {
+            account_info(pda(&[b"sol_vault", market_key.as_ref()]), System::id(), 1, Vec::new())
+        } else {
+            token_account(Pubkey::new_unique(), collateral_mint, beneficiary)
+        };
+        
+        vec![
+            program_account(market_key, market),
+            program_account(pda(&[b"creator_profile", market.creator.as_ref()]), &creator_profile),
+            account_info(pda(&[b"prediction", market_key.as_ref(), beneficiary.as_ref()]), System::id(), 0, Vec::new()),
+            token_account(market_vault, collateral_mint, market_key),
+            mint_account(collateral_mint),
+            collateral,
+            mint_account(outcome_mint),
+            token_account(Pubkey::new_unique(), outcome_mint, beneficiary),
+        ]
+    }
+
+    fn batch_entry(group: u8) -> BatchStakeEntry {
+        BatchStakeEntry { group, outcome_index: 0, amount: 1_000, max_outcome_share_bps: None }
+    }
+
+    // remaining_accounts holding one group per market, with only the market key filled in
+    fn batch_accounts(markets: &[Pubkey]) -> Vec<AccountInfo<'static>> {
+        markets
+            .iter()
+            .flat_map(|market| {
+                std::iter::once(*market)
+                    .chain((1..BATCH_STAKE_ACCOUNTS).map(|_| Pubkey::new_unique()))
+                    .map(|key| account_info(key, System::id(), 0, Vec::new()))
+            })
+            .collect()
+    }
+
+    fn load(entry: &BatchStakeEntry, group: Vec<AccountInfo<'static>>, beneficiary: Pubkey, token_program: Pubkey) -> Result<BatchGroup<'static>> {
+        load_batch_group(entry, Box::leak(group.into_boxed_slice()), &beneficiary, &token_program)
+    }
+
+    #[test]
+    fn batch_entries_need_a_group_each_and_distinct_markets() {
+        let (a, b, c) = (Pubkey::new_unique(), Pubkey::new_unique(), Pubkey::new_unique());
+        let accounts = batch_accounts(&[a, b]);
+        
+        // Entries can name their groups in any order
+        assert!(check_batch_entries(&[batch_entry(1), batch_entry(0)], &accounts).is_ok());
+        assert_eq!(
+            check_batch_entries(&[batch_entry(0), batch_entry(1)], &accounts[1..]).err(),
+            Some(ErrorCode::BatchAccountsMismatch.into())
+        );
+        assert_eq!(check_batch_entries(&[], &[]).err(), Some(ErrorCode::BatchAccountsMismatch.into()));
+        assert_eq!(
+            check_batch_entries(&[batch_entry(0), batch_entry(2)], &accounts).err(),
+            Some(ErrorCode::BatchAccountsMismatch.into())
+        );
+        
+        // The same group twice, or two groups for the same market
+        assert_eq!(
+            check_batch_entries(&[batch_entry(0), batch_entry(0)], &accounts).err(),
+            Some(ErrorCode::DuplicateBatchMarket.into())
+        );
+        assert_eq!(
+            check_batch_entries(&[batch_entry(0), batch_entry(1), batch_entry(2)], &batch_accounts(&[a, c, a])).err(),
+            Some(ErrorCode::DuplicateBatchMarket.into())
+        );
+    }
+
+    #[test]
+    fn batch_group_must_match_the_batch_token_program() {
+        let beneficiary = Pubkey::new_unique();
+        let market_key = Pubkey::new_unique();
+        let market = new_market(Pubkey::new_unique(), 0, 2);
+        
+        let loaded = load(&batch_entry(0), batch_group(&market, market_key, beneficiary), beneficiary, token::ID).unwrap();
+        assert!(loaded.user_token_account.is_some() && loaded.sol_vault.is_none());
+        
+        // Accounts swapped within the group
+        let mut swapped = batch_group(&market, market_key, beneficiary);
+        swapped.swap(3, 5);
+        assert!(load(&batch_entry(0), swapped, beneficiary, token::ID).is_err());
+        
+        // An SPL Token group in a batch signed for Token-2022
+        assert_eq!(
+            load(&batch_entry(0), batch_group(&market, market_key, beneficiary), beneficiary, anchor_spl::token_2022::ID).err(),
+            Some(ErrorCode::BatchAccountsMismatch.into())
+        );
+        
+        // Outcome tokens must go to the beneficiary
+        assert!(load(&batch_entry(0), batch_group(&market, market_key, beneficiary), Pubkey::new_unique(), token::ID).is_err());
+    }
+
+    #[test]
+    fn batch_group_takes_the_sol_vault_for_native_markets() {
+        let beneficiary = Pubkey::new_unique();
+        let market_key = Pubkey::new_unique();
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.native_sol = true;
+        
+        let loaded = load(&batch_entry(0), batch_group(&market, market_key, beneficiary), beneficiary, token::ID).unwrap();
+        assert!(loaded.sol_vault.is_some() && loaded.user_token_account.is_none());
+        
+        let mut with_token_account = batch_group(&market, market_key, beneficiary);
+        with_token_account[5] = token_account(Pubkey::new_unique(), Pubkey::new_unique(), beneficiary);
+        assert_eq!(
+            load(&batch_entry(0), with_token_account, beneficiary, token::ID).err(),
+            Some(ErrorCode::BatchAccountsMismatch.into())
+        );
+    }
+
+    #[test]
+    fn batch_group_accepts_a_prefunded_prediction_and_tops_up_an_existing_one() {
+        let beneficiary = Pubkey::new_unique();
+        let market_key = Pubkey::new_unique();
+        let market = new_market(Pubkey::new_unique(), 0, 2);
+        let prediction = pda(&[b"prediction", market_key.as_ref(), beneficiary.as_ref()]);
+        
+        let mut prefunded = batch_group(&market, market_key, beneficiary);
+        prefunded[2] = account_info(prediction, System::id(), 5_000, Vec::new());
+        assert!(load(&batch_entry(0), prefunded, beneficiary, token::ID).is_ok());
+        
+        let staked = Prediction { user: beneficiary, outcome_index: 0, amount: 500, ..Prediction::default() };
+        let mut existing = batch_group(&market, market_key, beneficiary);
+        existing[2] = program_account(prediction, &staked);
+        assert!(load(&batch_entry(0), existing, beneficiary, token::ID).is_ok());
+        
+        let mut other_outcome = batch_group(&market, market_key, beneficiary);
+        other_outcome[2] = program_account(prediction, &Prediction { outcome_index: 1, ..staked.clone() });
+        assert_eq!(
+            load(&batch_entry(0), other_outcome, beneficiary, token::ID).err(),
+            Some(ErrorCode::InvalidTopUp.into())
+        );
+        
+        let mut nft_backed = batch_group(&market, market_key, beneficiary);
+        nft_backed[2] = program_account(prediction, &Prediction { position_mint: Pubkey::new_unique(), ..staked });
+        assert_eq!(
+            load(&batch_entry(0), nft_backed, beneficiary, token::ID).err(),
+            Some(ErrorCode::InvalidTopUp.into())
+        );
+    }
+
+    #[test]
+    fn position_cap_counts_earlier_stakes() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.max_stake = 600;
+        market.max_position = 1_000;
+        let prediction = Prediction { user: Pubkey::new_unique(), outcome_index: 0, amount: 600, ..Prediction::default() };
+        
+        // Each stake fits under max_stake, but together they pass max_position
+        assert!(prediction.check_top_up(0).is_ok());
+        assert!(market.check_position(prediction.amount + 400).is_ok());
+        assert_eq!(
+            market.check_position(prediction.amount + 401).err(),
+            Some(ErrorCode::PositionCapExceeded.into())
+        );
+        assert!(Prediction::default().check_top_up(1).is_ok());
+    }
+
+    #[test]
+    fn resolved_token_predictions_close_even_when_winning() {
+        let mut market = new_market(Pubkey::new_unique(), 0, 2);
+        market.stakes_per_outcome = vec![100, 50];
+        let winner = Prediction { outcome_index: 0, amount: 100, ..Prediction::default() };
+        let loser = Prediction { outcome_index: 1, amount: 50, ..Prediction::default() };
+        assert!(market.check_prediction_closable(&winner).is_err());
+        
+        market.set_winning_outcome(Some(0)).unwrap();
+        market.resolved = true;
+        
+        // The winner's outcome tokens went to another wallet, which claims with them; the
+        // record no longer carries the claim, so its owner can still recover the rent
+        assert!(market.check_prediction_closable(&winner).is_ok());
+        assert!(market.check_prediction_closable(&loser).is_ok());
+        
+        // A winning NFT-backed record stays until claim_position_nft pays the NFT holder
+        let nft_winner = Prediction { position_mint: Pubkey::new_unique(), ..winner };
+        let nft_loser = Prediction { position_mint: Pubkey::new_unique(), ..loser };
+        assert_eq!(
+            market.check_prediction_closable(&nft_winner).err(),
+            Some(ErrorCode::NotLosingPrediction.into())
+        );
+        assert!(market.check_prediction_closable(&nft_loser).is_ok());
+    }
+
+    #[test]
+    fn escrowed_vote_tokens_cannot_be_voted_twice() {
+        let voter_a = Prediction { outcome_index: 0, amount: 100, ..Prediction::default() };
+        let voter_b = Prediction { outcome_index: 0, amount: 40, ..Prediction::default() };
+        let mut vote_result = VoteResult {
+            vote_tallies: vec![0; 3],
+            stake_weights: vec![0; 3],
+            ..VoteResult::default()
+        };
+        
+        // A bought extra tokens, but only the staked 100 count and move into escrow
+        let mut held_a = 150;
+        let weight_a = voter_a.vote_weight(held_a);
+        held_a -= weight_a;
+        vote_result.record_vote(0, 2, weight_a).unwrap();
+        assert_eq!(weight_a, 100);
+        
+        // A hands what is left to B, who can only vote tokens that are not in escrow
+        let held_b = 20 + held_a;
+        let weight_b = voter_b.vote_weight(held_b);
+        vote_result.record_vote(0, 2, weight_b).unwrap();
+        assert_eq!(weight_b, 40);
+        
+        // Every unit of weight is backed by a distinct escrowed token
+        assert_eq!(vote_result.stake_weights[0], weight_a + weight_b);
+        assert!(vote_result.stake_weights[0] <= 150 + 20);
+        assert_eq!(voter_b.vote_weight(0), 0);
+    }
+
+    #[test]
+    fn inserting_into_a_full_ask_side_needs_a_better_price_and_queue_room() {
+        let mut book = OrderBook::default();
+        let maker = Pubkey::new_unique();
+        let order = |order_id, price| Order { order_id, position: maker, price, quantity: 10 };
+        
+        for order_id in 0..MAX_ORDERS_PER_SIDE as u64 {
+            book.insert_order(OrderSide::Ask as u8, order(order_id, 500_000 + order_id)).unwrap();
+        }
+        let worst = 500_000 + MAX_ORDERS_PER_SIDE as u64 - 1;
+        
+        // An ask at or above the worst resting price has nowhere to go
+        assert_eq!(
+            book.insert_order(OrderSide::Ask as u8, order(100, worst)).err(),
+            Some(ErrorCode::OrderBookFull.into())
+        );
+        
+        // A better ask still needs room in the queue to hand back the evicted order
+        book.events = vec![FillEvent::default(); MAX_FILL_EVENTS];
+        assert_eq!(
+            book.insert_order(OrderSide::Ask as u8, order(101, 1)).err(),
+            Some(ErrorCode::EventQueueFull.into())
+        );
+        assert_eq!(book.asks.len(), MAX_ORDERS_PER_SIDE);
+        
+        book.events.clear();
+        book.insert_order(OrderSide::Ask as u8, order(102, 1)).unwrap();
+        assert_eq!(book.asks.len(), MAX_ORDERS_PER_SIDE);
+        assert_eq!(book.asks[0].order_id, 102);
+        assert_eq!(book.asks[MAX_ORDERS_PER_SIDE - 1].price, worst - 1);
+        assert!(book.events[0].evicted);
+        assert_eq!(book.events[0].price, worst);
+        
+        // The full book still serializes into the space allocated for it
+        assert!(book.try_to_vec().unwrap().len() <= OrderBook::SPACE);
+    }
+
+    #[test]
+    fn position_nft_votes_with_the_whole_stake() {
+        let prediction = Prediction {
+            outcome_index: 1,
+            amount: 250,
+            position_mint: Pubkey::new_unique(),
+            ..Prediction::default()
+        };
+        
+        // The NFT is a single token, so its balance says nothing about the stake behind it
+        assert_eq!(prediction.vote_weight(1), 250);
+        assert_eq!(prediction.vote_weight(0), 0);
+        
+        let mut vote_result = VoteResult {
+            vote_tallies: vec![0; 3],
+            stake_weights: vec![0; 3],
+            ..VoteResult::default()
+        };
+        vote_result.record_vote(1, 2, prediction.vote_weight(1)).unwrap();
+        assert_eq!(vote_result.stake_weights, vec![0, 250, 0]);
+        assert_eq!(vote_result.leading_outcome(2), 1);
+    }
+}
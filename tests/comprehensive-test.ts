@@ -133,6 +133,9 @@ async function testForesightProtocol() {
     );
     console.log("Tokens minted to all users");
     
+    const txRegisterMint = await client.registerCollateralMint(admin, mint, new anchor.BN(1_000_000));
+    console.log("Collateral mint registered:", txRegisterMint);
+    
 
     console.log("\nStep 5: Create Market");
     const question = "Will BTC exceed $100k by the end of 2025?";
@@ -202,6 +205,11 @@ async function testForesightProtocol() {
     
     const [marketVaultAddress] = await client.findMarketVaultAddress(marketAddress);
     
+    for (let outcomeIndex = 0; outcomeIndex < outcomes.length; outcomeIndex++) {
+      const txInitOutcomeMint = await client.initializeOutcomeMint(creator, marketAddress, outcomeIndex);
+      console.log(`Outcome ${outcomeIndex} mint initialized:`, txInitOutcomeMint);
+    }
+    
     const stake1Amount = new anchor.BN(100_000_000);
     const txStake1 = await client.stakePrediction(
       user1,
@@ -229,7 +237,54 @@ async function testForesightProtocol() {
       stakesPerOutcome: marketDataAfterStake.stakesPerOutcome.map(x => x.toString()),
     });
     
-    console.log("\nStep 9: Resolve Market with AI");
+    console.log("\nStep 9: Batch Stake Across Markets");
+    const batchMarkets: PublicKey[] = [];
+    for (const batchQuestion of ["Will ETH exceed $10k by the end of 2025?", "Will SOL exceed $500 by the end of 2025?"]) {
+      await client.createMarket(
+        creator,
+        mint,
+        batchQuestion,
+        outcomes,
+        aiScore,
+        resolutionTime,
+        aiClassification,
+        creatorMetadata
+      );
+      const { marketsCreated } = await client.program.account.creatorProfile.fetch(creatorProfileAddress);
+      const [batchMarket] = await client.findMarketAddress(creator.publicKey, marketsCreated - 1);
+      for (let outcomeIndex = 0; outcomeIndex < outcomes.length; outcomeIndex++) {
+        await client.initializeOutcomeMint(creator, batchMarket, outcomeIndex);
+      }
+      batchMarkets.push(batchMarket);
+    }
+    
+    const batchAmounts = [new anchor.BN(20_000_000), new anchor.BN(30_000_000)];
+    const txBatchStake = await client.batchStake(
+      user1,
+      batchMarkets.map((market, i) => ({
+        market,
+        sourceTokenAccount: user1Ata.address,
+        outcomeIndex: i,
+        amount: batchAmounts[i],
+      }))
+    );
+    console.log("User1 batch staked into two markets, transaction signature:", txBatchStake);
+    
+    for (const [i, batchMarket] of batchMarkets.entries()) {
+      const [predictionAddress] = await client.findPredictionAddress(batchMarket, user1.publicKey);
+      const prediction = await client.program.account.prediction.fetch(predictionAddress);
+      const batchMarketData = await client.program.account.market.fetch(batchMarket);
+      if (
+        prediction.outcomeIndex !== i ||
+        !prediction.amount.eq(batchAmounts[i]) ||
+        !batchMarketData.stakesPerOutcome[i].eq(batchAmounts[i])
+      ) {
+        throw new Error(`Batch stake into ${batchMarket.toString()} was not recorded`);
+      }
+    }
+    console.log("Batch stakes recorded on both markets");
+    
+    console.log("\nStep 10: Resolve Market with AI");
     const winningOutcomeIndex = 0;
     const txResolveWithAi = await client.resolveMarketViaAi(
       admin,
@@ -240,20 +295,38 @@ async function testForesightProtocol() {
     );
     console.log("Market resolved with AI, transaction signature:", txResolveWithAi);
     
-    console.log("\nStep 10: Claim Winnings");
+    console.log("\nStep 11: Claim Winnings");
     
-    const protocolFeeAddress = creatorAta.address;
+    try {
+      const txInitTreasury = await client.initializeTreasury(admin, mint);
+      console.log("Protocol treasury initialized:", txInitTreasury);
+    } catch (error) {
+      if (error.message.includes("already in use")) {
+        console.log("Protocol treasury already initialized, continuing...");
+      } else {
+        throw error;
+      }
+    }
+    
+    const [protocolTreasuryAddress] = await client.findTreasuryAddress(mint);
     
     const txClaimWinnings = await client.claimReward(
       user1,
       marketAddress,
       user1Ata.address,
-      creatorAta.address,
-      protocolFeeAddress
+      protocolTreasuryAddress,
+      winningOutcomeIndex
     );
     console.log("User1 claimed winnings, transaction signature:", txClaimWinnings);
     
-    console.log("\nStep 11: Verify Final Token Balances");
+    const txCreatorWithdraw = await client.creatorWithdraw(
+      creator,
+      marketAddress,
+      creatorAta.address
+    );
+    console.log("Creator withdrew accrued fees, transaction signature:", txCreatorWithdraw);
+    
+    console.log("\nStep 12: Verify Final Token Balances");
     const finalCreatorAta = await connection.getTokenAccountBalance(creatorAta.address);
     const finalUser1Ata = await connection.getTokenAccountBalance(user1Ata.address);
     const finalUser2Ata = await connection.getTokenAccountBalance(user2Ata.address);
@@ -263,7 +336,7 @@ async function testForesightProtocol() {
     console.log("- User1 (winner):", finalUser1Ata.value.uiAmount);
     console.log("- User2 (loser):", finalUser2Ata.value.uiAmount);
     
-    console.log("\nStep 12: Check Updated Protocol Stats");
+    console.log("\nStep 13: Check Updated Protocol Stats");
     const updatedProtocolStats = await client.program.account.protocolStats.fetch(protocolStatsAddress);
     console.log("Updated protocol stats:", {
       totalMarkets: updatedProtocolStats.totalMarkets,
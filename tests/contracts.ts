@@ -1,7 +1,12 @@
 import * as anchor from "@coral-xyz/anchor";
 import { Program, BN } from "@coral-xyz/anchor";
 import { PublicKey, SystemProgram } from "@solana/web3.js";
-import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import {
+  TOKEN_PROGRAM_ID,
+  createAssociatedTokenAccountIdempotentInstruction,
+  getAssociatedTokenAddressSync,
+  unpackAccount,
+} from "@solana/spl-token";
 import { Contracts } from "../target/types/contracts";
 
 export const PROGRAM_ID = new PublicKey("7Gh4eFGmobz5ngu2U3bgZiQm2Adwm33dQTsUwzRb7wBi");
@@ -9,8 +14,48 @@ export const PROGRAM_ID = new PublicKey("7Gh4eFGmobz5ngu2U3bgZiQm2Adwm33dQTsUwzR
 export const MarketType = {
   TimeBound: 0,
   OpenEnded: 1,
+  Binary: 2,
+  Scalar: 3,
 } as const;
 
+// Pass as the winning outcome to resolve a market as Invalid and refund every stake
+export const INVALID_OUTCOME = 255;
+
+export const FeeModel = {
+  TotalShare: 0,
+  ProfitOnly: 1,
+} as const;
+
+export const TradingMode = {
+  Parimutuel: 0,
+  Cpmm: 1,
+  Lmsr: 2,
+} as const;
+
+// Prices returned by quotePrices, and order book prices, are fractions of this
+export const PRICE_SCALE = 1_000_000;
+
+export const OrderSide = {
+  Bid: 0,
+  Ask: 1,
+} as const;
+
+// Optional per-market stake limits for createMarket; unset fields take the program defaults
+export type StakeLimits = {
+  minStake?: BN;
+  maxStake?: BN;
+  maxPosition?: BN;
+};
+
+// One stake for batchStake; pass a null sourceTokenAccount for native SOL markets
+export type BatchStakeEntry = {
+  market: PublicKey;
+  sourceTokenAccount: PublicKey | null;
+  outcomeIndex: number;
+  amount: BN;
+  maxOutcomeShareBps?: number;
+};
+
 export class PredictionMarketClient {
   readonly program: Program<Contracts>;
   readonly provider: anchor.AnchorProvider;
@@ -89,6 +134,111 @@ export class PredictionMarketClient {
     );
   }
 
+  async findTreasuryAuthorityAddress(): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury_authority")],
+      this.program.programId
+    );
+  }
+
+  async findTreasuryAddress(mint: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("treasury"), mint.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  async findCollateralMintAddress(mint: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("collateral_mint"), mint.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  async findSolVaultAddress(market: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("sol_vault"), market.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  // Native SOL markets move lamports through the SOL vault, so they take no token account
+  async solVaultFor(market: PublicKey, tokenAccount: PublicKey | null): Promise<PublicKey | null> {
+    return tokenAccount ? null : (await this.findSolVaultAddress(market))[0];
+  }
+
+  async findOutcomeMintAddress(
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("outcome_mint"), market.toBuffer(), Buffer.from([outcomeIndex])],
+      this.program.programId
+    );
+  }
+
+  // The SPL Token or Token-2022 program that owns a mint or token account
+  async tokenProgramOf(account: PublicKey): Promise<PublicKey> {
+    const info = await this.provider.connection.getAccountInfo(account);
+    return info.owner;
+  }
+
+  // A market's collateral mint and its token program, read from the market vault
+  async collateralOf(market: PublicKey): Promise<[PublicKey, PublicKey]> {
+    const [marketVault] = await this.findMarketVaultAddress(market);
+    const info = await this.provider.connection.getAccountInfo(marketVault);
+    const vault = unpackAccount(marketVault, info, info.owner);
+    return [vault.mint, info.owner];
+  }
+
+  // The owner's associated token account for an outcome, plus an idempotent create instruction.
+  // Outcome mints live under the same token program as the market's collateral
+  async outcomeTokenAccount(
+    payer: PublicKey,
+    owner: PublicKey,
+    market: PublicKey,
+    outcomeIndex: number,
+    tokenProgram: PublicKey = TOKEN_PROGRAM_ID
+  ): Promise<[PublicKey, PublicKey, anchor.web3.TransactionInstruction]> {
+    const [outcomeMint] = await this.findOutcomeMintAddress(market, outcomeIndex);
+    const tokenAccount = getAssociatedTokenAddressSync(outcomeMint, owner, false, tokenProgram);
+    const createIx = createAssociatedTokenAccountIdempotentInstruction(
+      payer,
+      tokenAccount,
+      owner,
+      outcomeMint,
+      tokenProgram
+    );
+    return [outcomeMint, tokenAccount, createIx];
+  }
+
+  async findPositionNftAddress(prediction: PublicKey): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("position_nft"), prediction.toBuffer()],
+      this.program.programId
+    );
+  }
+
+  async findOrderBookAddress(
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("order_book"), market.toBuffer(), Buffer.from([outcomeIndex])],
+      this.program.programId
+    );
+  }
+
+  async findPositionAddress(
+    market: PublicKey,
+    owner: PublicKey
+  ): Promise<[PublicKey, number]> {
+    return PublicKey.findProgramAddressSync(
+      [Buffer.from("position"), market.toBuffer(), owner.toBuffer()],
+      this.program.programId
+    );
+  }
+
   
 
   async createCreatorProfile(creator: anchor.web3.Keypair): Promise<string> {
@@ -132,6 +282,27 @@ export class PredictionMarketClient {
       .rpc();
   }
 
+  async resolveScalarMarketViaAi(
+    resolverAuthority: anchor.web3.Keypair,
+    market: PublicKey,
+    resolvedValue: BN,
+    aiConfidenceScore: number,
+    resolutionData: string
+  ): Promise<string> {
+    return this.program.methods
+      .resolveScalarMarketViaAi(
+        resolvedValue,
+        aiConfidenceScore,
+        resolutionData
+      )
+      .accounts({
+        resolverAuthority: resolverAuthority.publicKey,
+        market,
+      })
+      .signers([resolverAuthority])
+      .rpc();
+  }
+
   async createMarket(
     creator: anchor.web3.Keypair,
     mint: PublicKey,
@@ -142,223 +313,1051 @@ export class PredictionMarketClient {
     aiClassification: number,
     creatorMetadata: string,
     creatorFeeBps?: number,
-    aiResolvable?: boolean
+    aiResolvable?: boolean,
+    feeModel?: number,
+    scalarLowerBound?: BN,
+    scalarUpperBound?: BN,
+    tradingMode?: number,
+    lmsrLiquidity?: BN,
+    creatorTokenAccount?: PublicKey,
+    nativeSol?: boolean,
+    stakeLimits?: StakeLimits
   ): Promise<string> {
+    let solVault: PublicKey | null = null;
+    if (nativeSol) {
+      const [creatorProfile] = await this.findCreatorProfileAddress(creator.publicKey);
+      const { marketsCreated } = await this.program.account.creatorProfile.fetch(creatorProfile);
+      const [market] = await this.findMarketAddress(creator.publicKey, marketsCreated);
+      [solVault] = await this.findSolVaultAddress(market);
+    }
     return this.program.methods
-      .createMarket(
+      .createMarket({
         question,
         outcomes,
         aiScore,
         aiRecommendedResolutionTime,
         aiClassification,
         creatorMetadata,
-        creatorFeeBps ? creatorFeeBps : null,
-        aiResolvable !== undefined ? aiResolvable : null
-      )
-      .accounts({
+        creatorFeeBps: creatorFeeBps ? creatorFeeBps : null,
+        aiResolvable: aiResolvable !== undefined ? aiResolvable : null,
+        feeModel: feeModel !== undefined ? feeModel : null,
+        scalarLowerBound: scalarLowerBound ? scalarLowerBound : null,
+        scalarUpperBound: scalarUpperBound ? scalarUpperBound : null,
+        tradingMode: tradingMode !== undefined ? tradingMode : null,
+        lmsrLiquidity: lmsrLiquidity ? lmsrLiquidity : null,
+        nativeSol: nativeSol !== undefined ? nativeSol : null,
+        stakeLimits: stakeLimits
+          ? {
+              minStake: stakeLimits.minStake ?? null,
+              maxStake: stakeLimits.maxStake ?? null,
+              maxPosition: stakeLimits.maxPosition ?? null,
+            }
+          : null,
+      })
+      .accountsPartial({
         creator: creator.publicKey,
         mint,
+        creatorTokenAccount: creatorTokenAccount ? creatorTokenAccount : null,
+        solVault,
+        tokenProgram: await this.tokenProgramOf(mint),
       })
       .signers([creator])
       .rpc();
   }
 
+  async initializeOutcomeMint(
+    payer: anchor.web3.Keypair,
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    return this.program.methods
+      .initializeOutcomeMint(
+        outcomeIndex
+      )
+      .accountsPartial({
+        payer: payer.publicKey,
+        market,
+        collateralMint,
+        tokenProgram,
+      })
+      .signers([payer])
+      .rpc();
+  }
+
   async stakePrediction(
     user: anchor.web3.Keypair,
     market: PublicKey,
-    userTokenAccount: PublicKey,
+    userTokenAccount: PublicKey | null,
     outcomeIndex: number,
-    amount: BN
+    amount: BN,
+    maxOutcomeShareBps?: number
   ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const [outcomeMint, userOutcomeTokenAccount, createIx] =
+      await this.outcomeTokenAccount(user.publicKey, user.publicKey, market, outcomeIndex, tokenProgram);
     return this.program.methods
       .stakePrediction(
         outcomeIndex,
-        amount
+        amount,
+        maxOutcomeShareBps !== undefined ? maxOutcomeShareBps : null
       )
-      .accounts({
-        user: user.publicKey,
+      .accountsPartial({
+        payer: user.publicKey,
+        tokenAuthority: user.publicKey,
+        beneficiary: user.publicKey,
         market,
         userTokenAccount,
+        collateralMint,
+        solVault: await this.solVaultFor(market, userTokenAccount),
+        outcomeMint,
+        userOutcomeTokenAccount,
+        tokenProgram,
       })
+      .preInstructions([createIx])
       .signers([user])
       .rpc();
   }
 
-  async voteMarketOutcome(
-    voter: anchor.web3.Keypair,
+  // A relayer pays the rent and submits the stake, `tokenAuthority` funds it (as owner or
+  // delegate of the token account), and the position and winnings belong to `beneficiary`,
+  // which co-signs the transaction
+  async stakeOnBehalf(
+    payer: anchor.web3.Keypair,
+    tokenAuthority: anchor.web3.Keypair,
+    beneficiary: anchor.web3.Keypair,
     market: PublicKey,
-    outcomeIndex: number
+    sourceTokenAccount: PublicKey | null,
+    outcomeIndex: number,
+    amount: BN,
+    maxOutcomeShareBps?: number
   ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const [outcomeMint, userOutcomeTokenAccount, createIx] =
+      await this.outcomeTokenAccount(payer.publicKey, beneficiary.publicKey, market, outcomeIndex, tokenProgram);
+    const signers = [payer, tokenAuthority, beneficiary].filter(
+      (signer, i, all) => all.findIndex((other) => other.publicKey.equals(signer.publicKey)) === i
+    );
     return this.program.methods
-      .voteMarketOutcome(
-        outcomeIndex
+      .stakePrediction(
+        outcomeIndex,
+        amount,
+        maxOutcomeShareBps !== undefined ? maxOutcomeShareBps : null
       )
-      .accounts({
-        voter: voter.publicKey,
+      .accountsPartial({
+        payer: payer.publicKey,
+        tokenAuthority: tokenAuthority.publicKey,
+        beneficiary: beneficiary.publicKey,
         market,
+        userTokenAccount: sourceTokenAccount,
+        collateralMint,
+        solVault: await this.solVaultFor(market, sourceTokenAccount),
+        outcomeMint,
+        userOutcomeTokenAccount,
+        tokenProgram,
       })
-      .signers([voter])
+      .preInstructions([createIx])
+      .signers(signers)
       .rpc();
   }
 
-  async resolveMarket(
-    admin: anchor.web3.Keypair,
+  async stakeYes(
+    user: anchor.web3.Keypair,
     market: PublicKey,
-    winningOutcomeIndex?: number
+    userTokenAccount: PublicKey | null,
+    amount: BN,
+    maxOutcomeShareBps?: number
   ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const [outcomeMint, userOutcomeTokenAccount, createIx] =
+      await this.outcomeTokenAccount(user.publicKey, user.publicKey, market, 0, tokenProgram);
     return this.program.methods
-      .resolveMarket(
-        winningOutcomeIndex !== undefined ? winningOutcomeIndex : null
+      .stakeYes(
+        amount,
+        maxOutcomeShareBps !== undefined ? maxOutcomeShareBps : null
       )
-      .accounts({
-        admin: admin.publicKey,
+      .accountsPartial({
+        payer: user.publicKey,
+        tokenAuthority: user.publicKey,
+        beneficiary: user.publicKey,
         market,
+        userTokenAccount,
+        collateralMint,
+        solVault: await this.solVaultFor(market, userTokenAccount),
+        outcomeMint,
+        userOutcomeTokenAccount,
+        tokenProgram,
       })
-      .signers([admin])
+      .preInstructions([createIx])
+      .signers([user])
       .rpc();
   }
 
-  async claimReward(
+  async stakeNo(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey | null,
+    amount: BN,
+    maxOutcomeShareBps?: number
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const [outcomeMint, userOutcomeTokenAccount, createIx] =
+      await this.outcomeTokenAccount(user.publicKey, user.publicKey, market, 1, tokenProgram);
+    return this.program.methods
+      .stakeNo(
+        amount,
+        maxOutcomeShareBps !== undefined ? maxOutcomeShareBps : null
+      )
+      .accountsPartial({
+        payer: user.publicKey,
+        tokenAuthority: user.publicKey,
+        beneficiary: user.publicKey,
+        market,
+        userTokenAccount,
+        collateralMint,
+        solVault: await this.solVaultFor(market, userTokenAccount),
+        outcomeMint,
+        userOutcomeTokenAccount,
+        tokenProgram,
+      })
+      .preInstructions([createIx])
+      .signers([user])
+      .rpc();
+  }
+
+  async batchStake(
+    user: anchor.web3.Keypair,
+    entries: BatchStakeEntry[]
+  ): Promise<string> {
+    const writable = (pubkey: PublicKey) => ({ pubkey, isWritable: true, isSigner: false });
+    const readonly = (pubkey: PublicKey) => ({ pubkey, isWritable: false, isSigner: false });
+    const args = [];
+    const remainingAccounts = [];
+    const createIxs = [];
+    let tokenProgram = TOKEN_PROGRAM_ID;
+    for (const [group, entry] of entries.entries()) {
+      const marketData = await this.program.account.market.fetch(entry.market);
+      const [creatorProfile] = await this.findCreatorProfileAddress(marketData.creator);
+      const [prediction] = await this.findPredictionAddress(entry.market, user.publicKey);
+      const [marketVault] = await this.findMarketVaultAddress(entry.market);
+      const [collateralMint, entryTokenProgram] = await this.collateralOf(entry.market);
+      const collateralAccount = entry.sourceTokenAccount ??
+        (await this.findSolVaultAddress(entry.market))[0];
+      const [outcomeMint, userOutcomeTokenAccount, createIx] = await this.outcomeTokenAccount(
+        user.publicKey,
+        user.publicKey,
+        entry.market,
+        entry.outcomeIndex,
+        entryTokenProgram
+      );
+      tokenProgram = entryTokenProgram;
+      args.push({
+        group,
+        outcomeIndex: entry.outcomeIndex,
+        amount: entry.amount,
+        maxOutcomeShareBps: entry.maxOutcomeShareBps ?? null,
+      });
+      remainingAccounts.push(
+        writable(entry.market),
+        writable(creatorProfile),
+        writable(prediction),
+        writable(marketVault),
+        readonly(collateralMint),
+        writable(collateralAccount),
+        writable(outcomeMint),
+        writable(userOutcomeTokenAccount)
+      );
+      createIxs.push(createIx);
+    }
+    return this.program.methods
+      .batchStake(args)
+      .accountsPartial({
+        payer: user.publicKey,
+        tokenAuthority: user.publicKey,
+        beneficiary: user.publicKey,
+        tokenProgram,
+      })
+      .remainingAccounts(remainingAccounts)
+      .preInstructions(createIxs)
+      .signers([user])
+      .rpc();
+  }
+
+  async stakePredictionNft(
     user: anchor.web3.Keypair,
     market: PublicKey,
     userTokenAccount: PublicKey,
-    creatorTokenAccount: PublicKey,
-    protocolFeeAccount: PublicKey
+    outcomeIndex: number,
+    amount: BN,
+    maxOutcomeShareBps?: number
   ): Promise<string> {
+    // The position NFT is minted under the collateral's token program
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
     return this.program.methods
-      .claimReward()
-      .accounts({
-        user: user.publicKey,
+      .stakePredictionNft(
+        outcomeIndex,
+        amount,
+        maxOutcomeShareBps !== undefined ? maxOutcomeShareBps : null
+      )
+      .accountsPartial({
+        payer: user.publicKey,
+        tokenAuthority: user.publicKey,
+        beneficiary: user.publicKey,
         market,
         userTokenAccount,
-        creatorTokenAccount,
-        protocolFeeAccount,
+        collateralMint,
+        tokenProgram,
       })
       .signers([user])
       .rpc();
   }
 
-  async closeMarket(
-    admin: anchor.web3.Keypair,
+  // `prediction` is the original staker's prediction; `holder` is whoever now holds its NFT
+  async claimPositionNft(
+    holder: anchor.web3.Keypair,
     market: PublicKey,
-    marketVault: PublicKey
+    prediction: PublicKey,
+    holderTokenAccount: PublicKey,
+    protocolTreasury: PublicKey
   ): Promise<string> {
+    const [positionMint] = await this.findPositionNftAddress(prediction);
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const holderPositionTokenAccount = getAssociatedTokenAddressSync(
+      positionMint,
+      holder.publicKey,
+      false,
+      tokenProgram
+    );
     return this.program.methods
-      .closeMarket()
+      .claimPositionNft()
       .accountsPartial({
-        admin: admin.publicKey,
+        holder: holder.publicKey,
         market,
-        marketVault,
-      })      
-      .signers([admin])
+        prediction,
+        positionMint,
+        holderPositionTokenAccount,
+        collateralMint,
+        holderTokenAccount,
+        protocolTreasury,
+        tokenProgram,
+      })
+      .signers([holder])
       .rpc();
   }
 
-  async registerVoteAuthority(
-    admin: anchor.web3.Keypair,
+  // Moves the prediction together with the outcome tokens or position NFT that carry its claim
+  async transferPrediction(
+    user: anchor.web3.Keypair,
     market: PublicKey,
-    authority: PublicKey,
-    weight: number
+    newOwner: PublicKey
   ): Promise<string> {
+    const [prediction] = await this.findPredictionAddress(market, user.publicKey);
+    const { outcomeIndex, positionMint } = await this.program.account.prediction.fetch(prediction);
+    const hasNft = !positionMint.equals(PublicKey.default);
+    const [, tokenProgram] = await this.collateralOf(market);
+    const claimMint = hasNft
+      ? positionMint
+      : (await this.findOutcomeMintAddress(market, outcomeIndex))[0];
+    const userClaimTokenAccount = getAssociatedTokenAddressSync(claimMint, user.publicKey, false, tokenProgram);
+    const newOwnerClaimTokenAccount = getAssociatedTokenAddressSync(claimMint, newOwner, false, tokenProgram);
     return this.program.methods
-      .registerVoteAuthority(
-        weight
-      )
-      .accounts({
-        admin: admin.publicKey,
+      .transferPrediction()
+      .accountsPartial({
+        user: user.publicKey,
+        newOwner,
         market,
-        authority,
+        claimMint,
+        userClaimTokenAccount,
+        newOwnerClaimTokenAccount,
+        tokenProgram,
       })
-      .signers([admin])
+      .preInstructions([
+        createAssociatedTokenAccountIdempotentInstruction(
+          user.publicKey,
+          newOwnerClaimTokenAccount,
+          newOwner,
+          claimMint,
+          tokenProgram
+        ),
+      ])
+      .signers([user])
       .rpc();
   }
 
-  async initializeVoteResult(
-    admin: anchor.web3.Keypair,
+  async openPosition(
+    owner: anchor.web3.Keypair,
     market: PublicKey
   ): Promise<string> {
     return this.program.methods
-      .initializeVoteResult()
+      .openPosition()
       .accounts({
-        admin: admin.publicKey,
+        owner: owner.publicKey,
         market,
       })
-      .signers([admin])
+      .signers([owner])
       .rpc();
   }
 
-  async stakeWeightedVote(
-    voter: anchor.web3.Keypair,
+  async addLiquidity(
+    user: anchor.web3.Keypair,
     market: PublicKey,
-    outcomeIndex: number
+    userTokenAccount: PublicKey,
+    amount: BN,
+    minLpShares: BN = new BN(0)
   ): Promise<string> {
     return this.program.methods
-      .stakeWeightedVote(
-        outcomeIndex
+      .addLiquidity(
+        amount,
+        minLpShares
       )
       .accounts({
-        voter: voter.publicKey,
+        user: user.publicKey,
         market,
+        userTokenAccount,
       })
-      .signers([voter])
+      .signers([user])
       .rpc();
   }
 
-  async proposeResolution(
-    authority: anchor.web3.Keypair,
+  async removeLiquidity(
+    user: anchor.web3.Keypair,
     market: PublicKey,
-    outcomeIndex: number
+    userTokenAccount: PublicKey,
+    lpShares: BN
   ): Promise<string> {
     return this.program.methods
-      .proposeResolution(
-        outcomeIndex
+      .removeLiquidity(
+        lpShares
       )
       .accounts({
-        authority: authority.publicKey,
+        user: user.publicKey,
         market,
+        userTokenAccount,
       })
-      .signers([authority])
+      .signers([user])
       .rpc();
   }
 
-  async finalizeResolution(
-    admin: anchor.web3.Keypair,
-    market: PublicKey
+  async buyShares(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey,
+    outcomeIndex: number,
+    amount: BN,
+    minSharesOut: BN
   ): Promise<string> {
     return this.program.methods
-      .finalizeResolution()
+      .buyShares(
+        outcomeIndex,
+        amount,
+        minSharesOut
+      )
       .accounts({
-        admin: admin.publicKey,
+        user: user.publicKey,
         market,
+        userTokenAccount,
       })
-      .signers([admin])
+      .signers([user])
       .rpc();
   }
 
-  async challengeResolution(
-    challenger: anchor.web3.Keypair,
+  async sellShares(
+    user: anchor.web3.Keypair,
     market: PublicKey,
-    evidence: string
+    userTokenAccount: PublicKey,
+    outcomeIndex: number,
+    returnAmount: BN,
+    maxSharesIn: BN
   ): Promise<string> {
     return this.program.methods
-      .challengeResolution(
-        evidence
+      .sellShares(
+        outcomeIndex,
+        returnAmount,
+        maxSharesIn
       )
       .accounts({
-        challenger: challenger.publicKey,
+        user: user.publicKey,
         market,
+        userTokenAccount,
       })
-      .signers([challenger])
+      .signers([user])
       .rpc();
   }
 
-  async initializeProtocolStats(
-    admin: anchor.web3.Keypair
+  async mintCompleteSet(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey,
+    amount: BN
   ): Promise<string> {
     return this.program.methods
-      .initializeProtocolStats()
+      .mintCompleteSet(
+        amount
+      )
       .accounts({
+        user: user.publicKey,
+        market,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async redeemCompleteSet(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey,
+    amount: BN
+  ): Promise<string> {
+    return this.program.methods
+      .redeemCompleteSet(
+        amount
+      )
+      .accounts({
+        user: user.publicKey,
+        market,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async initializeOrderBook(
+    payer: anchor.web3.Keypair,
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    return this.program.methods
+      .initializeOrderBook(
+        outcomeIndex
+      )
+      .accounts({
+        payer: payer.publicKey,
+        market,
+      })
+      .signers([payer])
+      .rpc();
+  }
+
+  async placeOrder(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey,
+    outcomeIndex: number,
+    side: number,
+    price: BN,
+    quantity: BN
+  ): Promise<string> {
+    const [orderBook] = await this.findOrderBookAddress(market, outcomeIndex);
+    return this.program.methods
+      .placeOrder(
+        side,
+        price,
+        quantity
+      )
+      .accountsPartial({
+        user: user.publicKey,
+        market,
+        orderBook,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async cancelOrder(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey,
+    outcomeIndex: number,
+    orderId: BN
+  ): Promise<string> {
+    const [orderBook] = await this.findOrderBookAddress(market, outcomeIndex);
+    return this.program.methods
+      .cancelOrder(
+        orderId
+      )
+      .accountsPartial({
+        user: user.publicKey,
+        market,
+        orderBook,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  // Credits the makers of up to `limit` queued fills; their positions are read from the queue
+  async consumeEvents(
+    market: PublicKey,
+    outcomeIndex: number,
+    limit: number = 8
+  ): Promise<string> {
+    const [orderBook] = await this.findOrderBookAddress(market, outcomeIndex);
+    const book = await this.program.account.orderBook.fetch(orderBook);
+    const makers = [...new Set(
+      book.events.slice(0, limit).map((event) => event.makerPosition.toBase58())
+    )];
+    return this.program.methods
+      .consumeEvents(
+        limit
+      )
+      .accountsPartial({
+        market,
+        orderBook,
+      })
+      .remainingAccounts(makers.map((maker) => ({
+        pubkey: new PublicKey(maker),
+        isWritable: true,
+        isSigner: false,
+      })))
+      .rpc();
+  }
+
+  async settleFunds(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .settleFunds()
+      .accounts({
+        user: user.publicKey,
+        market,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async redeemShares(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .redeemShares()
+      .accounts({
+        user: user.publicKey,
+        market,
+        userTokenAccount,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async quotePrices(market: PublicKey): Promise<BN[]> {
+    return this.program.methods
+      .quotePrices()
+      .accounts({
+        market,
+      })
+      .view();
+  }
+
+  async voteMarketOutcome(
+    voter: anchor.web3.Keypair,
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    return this.program.methods
+      .voteMarketOutcome(
+        outcomeIndex
+      )
+      .accounts({
+        voter: voter.publicKey,
+        market,
+      })
+      .signers([voter])
+      .rpc();
+  }
+
+  async resolveMarket(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    winningOutcomeIndex?: number
+  ): Promise<string> {
+    return this.program.methods
+      .resolveMarket(
+        winningOutcomeIndex !== undefined ? winningOutcomeIndex : null
+      )
+      .accounts({
+        admin: admin.publicKey,
+        market,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async resolveScalarMarket(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    resolvedValue: BN
+  ): Promise<string> {
+    return this.program.methods
+      .resolveScalarMarket(
+        resolvedValue
+      )
+      .accounts({
+        admin: admin.publicKey,
+        market,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async resolveMarketWithPayouts(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    payoutNumerators: BN[]
+  ): Promise<string> {
+    return this.program.methods
+      .resolveMarketWithPayouts(
+        payoutNumerators
+      )
+      .accounts({
+        admin: admin.publicKey,
+        market,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async claimReward(
+    user: anchor.web3.Keypair,
+    market: PublicKey,
+    userTokenAccount: PublicKey | null,
+    protocolTreasury: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    const [outcomeMint] = await this.findOutcomeMintAddress(market, outcomeIndex);
+    const userOutcomeTokenAccount = getAssociatedTokenAddressSync(
+      outcomeMint,
+      user.publicKey,
+      false,
+      tokenProgram
+    );
+    return this.program.methods
+      .claimReward()
+      .accountsPartial({
+        user: user.publicKey,
+        market,
+        outcomeMint,
+        userOutcomeTokenAccount,
+        prediction: await this.predictionToClose(market, user.publicKey),
+        collateralMint,
+        userTokenAccount,
+        solVault: await this.solVaultFor(market, userTokenAccount),
+        protocolTreasury,
+        tokenProgram,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  // The claimer's own prediction, which claim_reward closes unless a position NFT backs it
+  async predictionToClose(market: PublicKey, user: PublicKey): Promise<PublicKey | null> {
+    const [prediction] = await this.findPredictionAddress(market, user);
+    const account = await this.program.account.prediction.fetchNullable(prediction);
+    return account && account.positionMint.equals(PublicKey.default) ? prediction : null;
+  }
+
+  async closeLosingPrediction(
+    user: anchor.web3.Keypair,
+    market: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .closeLosingPrediction()
+      .accounts({
+        user: user.publicKey,
+        market,
+      })
+      .signers([user])
+      .rpc();
+  }
+
+  async creatorWithdraw(
+    creator: anchor.web3.Keypair,
+    market: PublicKey,
+    creatorTokenAccount: PublicKey | null
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    return this.program.methods
+      .creatorWithdraw()
+      .accountsPartial({
+        creator: creator.publicKey,
+        market,
+        collateralMint,
+        creatorTokenAccount,
+        solVault: await this.solVaultFor(market, creatorTokenAccount),
+        tokenProgram,
+      })
+      .signers([creator])
+      .rpc();
+  }
+
+  async sweepMarketVault(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    protocolTreasury: PublicKey,
+    nativeSol?: boolean
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    return this.program.methods
+      .sweepMarketVault()
+      .accountsPartial({
+        admin: admin.publicKey,
+        market,
+        collateralMint,
+        protocolTreasury,
+        solVault: nativeSol ? (await this.findSolVaultAddress(market))[0] : null,
+        tokenProgram,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async closeMarket(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    marketVault: PublicKey,
+    creator: PublicKey,
+    nativeSol?: boolean
+  ): Promise<string> {
+    const [collateralMint, tokenProgram] = await this.collateralOf(market);
+    return this.program.methods
+      .closeMarket()
+      .accountsPartial({
+        admin: admin.publicKey,
+        market,
+        creator,
+        marketVault,
+        collateralMint,
+        solVault: nativeSol ? (await this.findSolVaultAddress(market))[0] : null,
+        tokenProgram,
+      })      
+      .signers([admin])
+      .rpc();
+  }
+
+  async registerVoteAuthority(
+    admin: anchor.web3.Keypair,
+    market: PublicKey,
+    authority: PublicKey,
+    weight: number
+  ): Promise<string> {
+    return this.program.methods
+      .registerVoteAuthority(
+        weight
+      )
+      .accounts({
+        admin: admin.publicKey,
+        market,
+        authority,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async initializeVoteResult(
+    admin: anchor.web3.Keypair,
+    market: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .initializeVoteResult()
+      .accounts({
+        admin: admin.publicKey,
+        market,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async stakeWeightedVote(
+    voter: anchor.web3.Keypair,
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    // Weight comes from the voter's outcome tokens or position NFT, held in escrow until voting ends
+    const [claimMint, voterClaimTokenAccount, tokenProgram] = await this.voteClaimAccounts(voter.publicKey, market);
+    return this.program.methods
+      .stakeWeightedVote(
+        outcomeIndex
+      )
+      .accounts({
+        voter: voter.publicKey,
+        market,
+        claimMint,
+        voterClaimTokenAccount,
+        tokenProgram,
+      })
+      .signers([voter])
+      .rpc();
+  }
+
+  async reclaimVoteTokens(
+    voter: anchor.web3.Keypair,
+    market: PublicKey
+  ): Promise<string> {
+    const [claimMint, voterClaimTokenAccount, tokenProgram] = await this.voteClaimAccounts(voter.publicKey, market);
+    return this.program.methods
+      .reclaimVoteTokens()
+      .accounts({
+        voter: voter.publicKey,
+        market,
+        claimMint,
+        voterClaimTokenAccount,
+        tokenProgram,
+      })
+      .signers([voter])
+      .rpc();
+  }
+
+  async voteClaimAccounts(
+    voter: PublicKey,
+    market: PublicKey
+  ): Promise<[PublicKey, PublicKey, PublicKey]> {
+    const [prediction] = await this.findPredictionAddress(market, voter);
+    const { outcomeIndex, positionMint } = await this.program.account.prediction.fetch(prediction);
+    const [, tokenProgram] = await this.collateralOf(market);
+    const claimMint = positionMint.equals(PublicKey.default)
+      ? (await this.findOutcomeMintAddress(market, outcomeIndex))[0]
+      : positionMint;
+    const voterClaimTokenAccount = getAssociatedTokenAddressSync(claimMint, voter, false, tokenProgram);
+    return [claimMint, voterClaimTokenAccount, tokenProgram];
+  }
+
+  async proposeResolution(
+    authority: anchor.web3.Keypair,
+    market: PublicKey,
+    outcomeIndex: number
+  ): Promise<string> {
+    return this.program.methods
+      .proposeResolution(
+        outcomeIndex
+      )
+      .accounts({
+        authority: authority.publicKey,
+        market,
+      })
+      .signers([authority])
+      .rpc();
+  }
+
+  async finalizeResolution(
+    admin: anchor.web3.Keypair,
+    market: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .finalizeResolution()
+      .accounts({
+        admin: admin.publicKey,
+        market,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async challengeResolution(
+    challenger: anchor.web3.Keypair,
+    market: PublicKey,
+    evidence: string
+  ): Promise<string> {
+    return this.program.methods
+      .challengeResolution(
+        evidence
+      )
+      .accounts({
+        challenger: challenger.publicKey,
+        market,
+      })
+      .signers([challenger])
+      .rpc();
+  }
+
+  async initializeProtocolStats(
+    admin: anchor.web3.Keypair
+  ): Promise<string> {
+    return this.program.methods
+      .initializeProtocolStats()
+      .accounts({
+        admin: admin.publicKey,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async initializeTreasury(
+    admin: anchor.web3.Keypair,
+    mint: PublicKey
+  ): Promise<string> {
+    return this.program.methods
+      .initializeTreasury()
+      .accountsPartial({
+        admin: admin.publicKey,
+        mint,
+        tokenProgram: await this.tokenProgramOf(mint),
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async withdrawTreasury(
+    admin: anchor.web3.Keypair,
+    treasury: PublicKey,
+    destination: PublicKey,
+    amount: BN
+  ): Promise<string> {
+    const info = await this.provider.connection.getAccountInfo(treasury);
+    const { mint } = unpackAccount(treasury, info, info.owner);
+    return this.program.methods
+      .withdrawTreasury(
+        amount
+      )
+      .accountsPartial({
+        admin: admin.publicKey,
+        treasury,
+        mint,
+        destination,
+        tokenProgram: info.owner,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async registerCollateralMint(
+    admin: anchor.web3.Keypair,
+    mint: PublicKey,
+    minStake: BN
+  ): Promise<string> {
+    return this.program.methods
+      .registerCollateralMint(
+        minStake
+      )
+      .accounts({
+        admin: admin.publicKey,
+        mint,
+      })
+      .signers([admin])
+      .rpc();
+  }
+
+  async updateCollateralMint(
+    admin: anchor.web3.Keypair,
+    mint: PublicKey,
+    minStake: BN,
+    enabled: boolean
+  ): Promise<string> {
+    const [collateralConfig] = await this.findCollateralMintAddress(mint);
+    return this.program.methods
+      .updateCollateralMint(
+        minStake,
+        enabled
+      )
+      .accountsPartial({
         admin: admin.publicKey,
+        collateralConfig,
       })
       .signers([admin])
       .rpc();